@@ -0,0 +1,253 @@
+use clap::Parser;
+
+#[derive(clap::Parser)]
+#[command(about = "\
+Backport merged pull requests with a needs-backport label to their release branch:
+* Cherry-pick the commits with -x onto the release branch.\n\
+* On success, push a branch and open a backport pull request.\n\
+* On conflicts, comment the conflicting files on the merged pull request.\n\
+", long_about = None)]
+struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: util::Slug,
+    /// Only backport this merged pull request number.
+    #[arg(long)]
+    pull_num: Option<u64>,
+    /// The local dir used for scratching.
+    #[arg(long)]
+    scratch_dir: std::path::PathBuf,
+    /// The path to the yaml config file.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct Config {
+    needs_backport_label: String,
+    /// The fork to push the backport branches to. Format: owner/repo
+    push_repo: String,
+    push_url: String,
+    /// The description should contain {pull} and {branch}, which will be substituted.
+    backport_description: String,
+    /// The comment should contain {branch} and {files}, which will be substituted.
+    conflict_comment: String,
+}
+
+/// Return the commits of the merged pull request, oldest first.
+fn merged_commits(merge_commit: &str) -> Vec<String> {
+    let parents =
+        util::check_output(util::git().args(["rev-list", "--parents", "-n1", merge_commit]));
+    if parents.split_whitespace().count() == 3 {
+        util::check_output(util::git().args([
+            "rev-list",
+            "--reverse",
+            &format!("{merge_commit}^1..{merge_commit}^2"),
+        ]))
+        .lines()
+        .map(|c| c.to_string())
+        .collect()
+    } else {
+        // Squash-merged or rebased
+        vec![merge_commit.to_string()]
+    }
+}
+
+/// Cherry-pick the commits onto the release branch. Return the conflicting files on failure.
+fn cherry_pick(branch: &str, commits: &[String]) -> Result<(), Vec<String>> {
+    util::check_call(util::git().args([
+        "checkout",
+        "--quiet",
+        "--force",
+        "--detach",
+        &format!("origin/{branch}"),
+    ]));
+    for commit in commits {
+        if !util::call(util::git().args(["cherry-pick", "-x", commit])) {
+            let files =
+                util::check_output(util::git().args(["diff", "--name-only", "--diff-filter=U"]));
+            util::check_call(util::git().args(["cherry-pick", "--abort"]));
+            return Err(files.lines().map(|f| f.to_string()).collect());
+        }
+    }
+    Ok(())
+}
+
+async fn backport_pull(
+    config: &Config,
+    github: &octocrab::Octocrab,
+    args: &Args,
+    branch: &str,
+    number: u64,
+) -> octocrab::Result<()> {
+    let util::Slug { owner, repo } = &args.github_repo;
+    let sl = args.github_repo.str();
+    let issues_api = github.issues(owner, repo);
+    let pulls_api = github.pulls(owner, repo);
+    let head_name = format!("backport-{branch}-{number}");
+    let push_owner = config
+        .push_repo
+        .split_once('/')
+        .expect("push_repo format error")
+        .0;
+
+    let found = util::find_backports(github, &sl, number, branch).await?;
+    if !found.is_empty() {
+        println!("... backport exists: {found:?}");
+        return Ok(());
+    }
+    let opened = pulls_api
+        .list()
+        .state(octocrab::params::State::All)
+        .head(format!("{push_owner}:{head_name}"))
+        .send()
+        .await?;
+    if !opened.items.is_empty() {
+        println!("... backport pull {head_name} exists");
+        return Ok(());
+    }
+    let id_backport_failed = util::IdComment::BackportFailed.str();
+    let comments = github
+        .all_pages(issues_api.list_comments(number).send().await?)
+        .await?;
+    if comments.iter().any(|c| {
+        c.body.as_ref().is_some_and(|b| {
+            b.starts_with(id_backport_failed) && b.contains(&format!("`{branch}`"))
+        })
+    }) {
+        println!("... backport to {branch} failed earlier");
+        return Ok(());
+    }
+
+    let pull = pulls_api.get(number).await?;
+    let merge_commit = pull.merge_commit_sha.as_ref().expect("remote api error");
+    let commits = merged_commits(merge_commit);
+    match cherry_pick(branch, &commits) {
+        Err(files) => {
+            println!("... conflicts in {files:?}");
+            let text = format!(
+                "{id_backport_failed}\n{}",
+                config.conflict_comment.replace("{branch}", branch).replace(
+                    "{files}",
+                    &files
+                        .iter()
+                        .map(|f| format!("* `{f}`"))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )
+            );
+            if !args.dry_run {
+                issues_api.create_comment(number, text).await?;
+            }
+        }
+        Ok(()) => {
+            let title = format!("[{branch}] {}", pull.title.as_deref().unwrap_or_default());
+            let body = format!(
+                "{}\n{}\n",
+                config
+                    .backport_description
+                    .replace("{pull}", &format!("{sl}#{number}"))
+                    .replace("{branch}", branch),
+                commits
+                    .iter()
+                    .map(|c| format!(
+                        "* {c} {s}",
+                        s = util::check_output(util::git().args(["log", "-1", "--format=%s", c]))
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            println!("... push {head_name} and open pull '{title}'");
+            if !args.dry_run {
+                util::check_call(util::git().args([
+                    "push",
+                    "--quiet",
+                    "--force",
+                    &config.push_url,
+                    &format!("HEAD:refs/heads/{head_name}"),
+                ]));
+                pulls_api
+                    .create(title, format!("{push_owner}:{head_name}"), branch)
+                    .body(body)
+                    .send()
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> octocrab::Result<()> {
+    let args = Args::parse();
+
+    let config: Config = serde_yaml::from_reader(
+        std::fs::File::open(&args.config_file).expect("config file path error"),
+    )
+    .expect("yaml error");
+
+    let github = util::get_octocrab(args.github_access_token.clone())?;
+
+    std::fs::create_dir_all(&args.scratch_dir).expect("invalid scratch_dir");
+
+    let monotree_dir = args
+        .scratch_dir
+        .canonicalize()
+        .expect("invalid scratch_dir")
+        .join(format!(
+            "{}_{}",
+            args.github_repo.owner, args.github_repo.repo
+        ))
+        .join("persist");
+
+    util::init_monotree(&monotree_dir, std::slice::from_ref(&args.github_repo));
+    util::chdir(&monotree_dir);
+    util::check_call(util::git().args(["fetch", "--quiet", "origin"]));
+
+    let util::Slug { owner, repo } = &args.github_repo;
+    let labels = github
+        .all_pages(
+            github
+                .issues(owner, repo)
+                .list_labels_for_repo()
+                .send()
+                .await?,
+        )
+        .await?;
+    for label in &labels {
+        let Some(branch) = util::needs_backport_branch(&config.needs_backport_label, &label.name)
+        else {
+            continue;
+        };
+        let items = github
+            .all_pages(
+                github
+                    .search()
+                    .issues_and_pull_requests(&format!(
+                        "repo:{owner}/{repo} is:pr is:merged label:\"{}\"",
+                        label.name
+                    ))
+                    .send()
+                    .await?,
+            )
+            .await?;
+        for item in items
+            .iter()
+            .filter(|i| args.pull_num.is_none_or(|n| n == i.number))
+        {
+            println!(
+                "Backport {sl}#{num} to {branch}",
+                sl = args.github_repo.str(),
+                num = item.number
+            );
+            backport_pull(&config, &github, &args, branch, item.number).await?;
+        }
+    }
+    Ok(())
+}
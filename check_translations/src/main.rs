@@ -1,11 +1,9 @@
 use clap::Parser;
-use serde_json::json;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
-use std::process::{Command, Stdio};
-use std::thread::sleep;
 use std::time::{Duration, Instant};
+use util::llm::{LlmClient, LlmConfig, LlmMessage, LlmProvider};
 
 #[derive(Parser)]
 #[command(about="Check Bitcoin Core GUI translations.", long_about = None)]
@@ -14,6 +12,18 @@ struct Args {
     #[arg(long)]
     llm_api_key: String,
 
+    /// The LLM provider (openai, gemini, or local)
+    #[arg(long, default_value = "gemini")]
+    llm_provider: LlmProvider,
+
+    /// The LLM model to use
+    #[arg(long, default_value = "gemini-3-flash-preview")]
+    llm_model: String,
+
+    /// Can change to "high" after a first pre-filter run, for better results.
+    #[arg(long, default_value = "low")]
+    llm_reasoning_effort: String,
+
     /// The 'locale' folder that contains *.ts files
     #[arg(long)]
     translation_dir: String,
@@ -35,9 +45,18 @@ struct Args {
     rate_limit: u64,
 }
 
-fn main() {
+#[tokio::main]
+async fn main() {
     let args = Args::parse();
 
+    let llm = LlmClient::new(
+        LlmConfig {
+            reasoning_effort: Some(args.llm_reasoning_effort),
+            ..LlmConfig::new(args.llm_provider, &args.llm_model)
+        },
+        &args.llm_api_key,
+    );
+
     let ts_dir = fs::canonicalize(args.translation_dir).expect("locale dir must exist");
     let cache_dir = fs::canonicalize(args.cache_dir).expect("cache dir must exist (can be empty)");
     let report_folder = fs::canonicalize(args.report_folder)
@@ -78,10 +97,11 @@ fn main() {
             lang,
             &cache_dir.join(lang),
             &ts,
-            &args.llm_api_key,
+            &llm,
             &report_file,
             Duration::from_secs(args.rate_limit),
-        );
+        )
+        .await;
     }
 }
 
@@ -135,11 +155,11 @@ fn print_result(
     }
 }
 
-fn check(
+async fn check(
     lang: &str,
     lang_cache_dir: &Path,
     ts: &str,
-    token: &str,
+    llm: &LlmClient,
     mut report_file: &fs::File,
     rate_limit_wait: Duration,
 ) {
@@ -151,11 +171,7 @@ fn check(
     // However, the stronger Gemini models come with strict rate limits in Tier 1.
     //
     // From https://ai.google.dev/gemini-api/docs/rate-limits#tier-1
-    let url = "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions";
-    let model = "gemini-3-flash-preview";
-
-    //let url = "https://api.openai.com/v1/chat/completions";
-    //let model = "gpt-5.1";
+    println!("Using model {model}", model = llm.model());
 
     report_file
         .write_all(format!("\n\n<details><summary>{lang}</summary>\n\n").as_bytes())
@@ -288,40 +304,16 @@ Evaluate this '{lang}' translation:
                     file = cache_file.display()
                 );
                 let sleep_target = Instant::now() + rate_limit_wait;
-                let payload = json!({
-                  "reasoning_effort": "low", // Can change to "high" after a first pre-filter run, for better results.
-                  "model": model,
-                  "messages": [
-                    {"role": "user", "content": prompt_overview },
-                    {"role": "user", "content": prompt_instructions },
-                    {"role": "user", "content": prompt_tx},
-                ]
-                });
-
-                let curl_out = Command::new("curl")
-                    .arg("--fail")
-                    .arg("-X")
-                    .arg("POST")
-                    .arg("-H")
-                    .arg("Content-Type: application/json")
-                    .arg(url)
-                    .arg("-H")
-                    .arg(format!("Authorization: Bearer {token}"))
-                    .arg("-d")
-                    .arg(serde_json::to_string(&payload).expect("Failed to serialize payload"))
-                    .stderr(Stdio::inherit())
-                    .output()
-                    .expect("Failed to execute curl");
-                assert!(curl_out.status.success());
-                let response: serde_json::Value = serde_json::from_str(
-                    &String::from_utf8(curl_out.stdout).expect("must be valid utf8"),
-                )
-                .expect("must be valid json");
-                println!("... {response}");
-                let val = response["choices"][0]["message"]["content"]
-                    .as_str()
-                    .expect("Content not found")
-                    .trim();
+                let reply = llm
+                    .chat(&[
+                        LlmMessage::user(prompt_overview),
+                        LlmMessage::user(prompt_instructions),
+                        LlmMessage::user(&prompt_tx),
+                    ])
+                    .await
+                    .expect("LLM request must succeed");
+                println!("... {}", reply.text);
+                let val = reply.text.trim();
                 fs::write(&cache_file, val).expect("Must be able to write cache file");
                 print_result(
                     &mut num_issues,
@@ -331,7 +323,7 @@ Evaluate this '{lang}' translation:
                     &msg,
                     report_file,
                 );
-                sleep(sleep_target - Instant::now());
+                tokio::time::sleep_until(sleep_target.into()).await;
             }
         }
     }
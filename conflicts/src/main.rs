@@ -1,5 +1,4 @@
 use clap::Parser;
-use std::io::Write;
 
 #[derive(clap::Parser)]
 #[command(about = "Determine conflicting pull requests in a monotree by merging them pairwise.", long_about = None)]
@@ -22,6 +21,10 @@ struct Args {
     /// The path to the yaml config file.
     #[arg(long)]
     config_file: std::path::PathBuf,
+    /// The yaml file with the layouts of the metadata comment per repo slug. If not given, the
+    /// builtin util/meta_layouts.yml is used.
+    #[arg(long)]
+    meta_layouts_file: Option<std::path::PathBuf>,
     /// Print changes/edits instead of calling the GitHub API.
     #[arg(long, default_value_t = false)]
     dry_run: bool,
@@ -41,40 +44,6 @@ struct Config {
     conflicts_empty: String,
 }
 
-fn init_git(monotree_dir: &std::path::Path, repos: &Vec<util::Slug>) {
-    if monotree_dir.is_dir() {
-        return;
-    }
-    for sl in repos {
-        let sl = sl.str();
-        let url = format!("https://github.com/{sl}");
-        println!("Clone {url} repo to {dir}", dir = monotree_dir.display());
-        if !monotree_dir.is_dir() {
-            util::check_call(
-                util::git()
-                    .args(["clone", "--quiet", &url])
-                    .arg(monotree_dir),
-            );
-        }
-        println!("Set git metadata");
-        util::chdir(monotree_dir);
-        {
-            let err = "git config file error";
-            let mut f = std::fs::OpenOptions::new()
-                .append(true)
-                .open(monotree_dir.join(".git").join("config"))
-                .expect(err);
-            writeln!(f, "[remote \"con_pull_ref/{sl}\"]").expect(err);
-            writeln!(f, "    url = {url}").expect(err);
-            writeln!(f, "    fetch = +refs/pull/*:refs/remotes/upstream-pull/*").expect(err);
-        }
-        util::check_call(util::git().args(["config", "fetch.showForcedUpdates", "false"]));
-        util::check_call(util::git().args(["config", "user.email", "no@ne.nl"]));
-        util::check_call(util::git().args(["config", "user.name", "none"]));
-        util::check_call(util::git().args(["config", "gc.auto", "0"]));
-    }
-}
-
 struct MetaPull {
     pull: octocrab::models::pulls::PullRequest,
     head_commit: String,
@@ -163,7 +132,21 @@ async fn update_comment(
     pulls_conflict: &[&MetaPull],
 ) -> octocrab::Result<()> {
     let api_issues = api.issues(&pull.slug.owner, &pull.slug.repo);
-    let mut cmt = util::get_metadata_sections(api, &api_issues, pull.pull.number).await?;
+    let mut cmt = util::get_metadata_sections(
+        api,
+        &api_issues,
+        pull.pull.number,
+        &util::MetaLayout::for_repo(&pull.slug.str()),
+    )
+    .await?;
+    cmt.set_state(
+        util::MetaStateKey::Conflicts,
+        pulls_conflict
+            .iter()
+            .map(|p| p.slug_num.as_str())
+            .collect::<Vec<_>>()
+            .into(),
+    );
     if pulls_conflict.is_empty() {
         if cmt.id.is_none() || !cmt.has_section(&util::IdComment::SecConflicts) {
             // No conflict and no section to update
@@ -218,6 +201,9 @@ async fn update_comment(
 #[tokio::main]
 async fn main() -> octocrab::Result<()> {
     let args = Args::parse();
+    if let Some(path) = &args.meta_layouts_file {
+        util::MetaLayout::load(path);
+    }
 
     let config: Config = serde_yaml::from_reader(
         std::fs::File::open(args.config_file).expect("config file path error"),
@@ -243,7 +229,7 @@ async fn main() -> octocrab::Result<()> {
     let temp_dir = monotree_dir.parent().unwrap().join("temp");
     std::fs::create_dir_all(&temp_dir).expect("invalid temp_dir");
 
-    init_git(&monotree_dir, &args.github_repo);
+    util::init_monotree(&monotree_dir, &args.github_repo);
 
     println!("Fetching diffs ...");
     util::chdir(&monotree_dir);
@@ -0,0 +1,270 @@
+use clap::Parser;
+
+#[derive(clap::Parser)]
+#[command(about = "\
+Generate the repo_labels of a repo in the webhook_features config from the pull request title \
+prefixes in CONTRIBUTING.md. Prefixes are mapped to the existing labels by their title regexes. \
+Prints the diff against the current config.", long_about = None)]
+struct Args {
+    /// The CONTRIBUTING.md file of a local checkout.
+    #[arg(long)]
+    contributing_file: std::path::PathBuf,
+    /// The webhook_features yaml config file.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// The repo slug in the config to generate the repo_labels for. Format: owner/repo
+    #[arg(long, default_value = "bitcoin/bitcoin")]
+    repo_slug: String,
+    /// Map a prefix that does not match any existing label. Format: prefix=Label
+    #[arg(long, value_parser=parse_map)]
+    map: Vec<(String, String)>,
+    /// Write the result to the config file, instead of only printing the diff.
+    #[arg(long, default_value_t = false)]
+    write: bool,
+}
+
+fn parse_map(val: &str) -> Result<(String, String), String> {
+    val.split_once('=')
+        .map(|(p, l)| (p.to_string(), l.to_string()))
+        .ok_or("Wrong format, see --help.".to_string())
+}
+
+/// Return the title prefixes listed in the "Creating the Pull Request" section, in order.
+fn parse_prefixes(contributing: &str) -> Vec<String> {
+    let code = regex::Regex::new("`([^`]+)`").unwrap();
+    let mut prefixes = Vec::new();
+    for line in contributing
+        .lines()
+        .skip_while(|l| !(l.starts_with('#') && l.contains("Creating the Pull Request")))
+        .skip(1)
+        .take_while(|l| !l.starts_with('#'))
+    {
+        let Some(item) = line.trim_start().strip_prefix("- ") else {
+            continue;
+        };
+        // Only the part before the description, like "`rpc`, `rest` or `zmq` for changes to ..."
+        let names = item.split(" for ").next().unwrap_or_default();
+        for cap in code.captures_iter(names) {
+            let p = cap[1].trim_end_matches(':').to_string();
+            if !prefixes.contains(&p) {
+                prefixes.push(p);
+            }
+        }
+    }
+    prefixes
+}
+
+/// Add a regex for every prefix not already covered by the labels. Returns the prefixes that
+/// could not be mapped.
+fn map_prefixes(
+    labels: &mut Vec<(String, Vec<String>)>,
+    prefixes: &[String],
+    manual: &[(String, String)],
+) -> Vec<String> {
+    let mut unmapped = Vec::new();
+    for p in prefixes {
+        let title = format!("{p}: ");
+        let matches = |regs: &Vec<String>| {
+            regs.iter().any(|r| {
+                regex::RegexBuilder::new(r)
+                    .case_insensitive(true)
+                    .build()
+                    .expect("regex config format error")
+                    .is_match(&title)
+            })
+        };
+        if labels.iter().any(|(_, regs)| matches(regs)) {
+            continue;
+        }
+        let Some((_, label)) = manual.iter().find(|(m, _)| m == p) else {
+            unmapped.push(p.clone());
+            continue;
+        };
+        let reg = format!("^{}:", regex::escape(p));
+        match labels.iter_mut().find(|(l, _)| l == label) {
+            Some((_, regs)) => regs.push(reg),
+            None => labels.push((label.clone(), vec![reg])),
+        }
+    }
+    unmapped
+}
+
+/// Return the label name as a yaml key, single-quoted like the regexes if it is not a plain
+/// string, for example because of a `:` or a leading `*`.
+fn yaml_key(label: &str) -> String {
+    let plain = serde_yaml::from_str::<serde_yaml::Mapping>(&format!("{label}: 0"))
+        .is_ok_and(|m| m.len() == 1 && m.keys().next().and_then(|k| k.as_str()) == Some(label));
+    if plain {
+        label.to_string()
+    } else {
+        format!("'{}'", label.replace('\'', "''"))
+    }
+}
+
+fn render_labels(labels: &[(String, Vec<String>)], indent: &str) -> String {
+    let mut out = format!("{indent}repo_labels:\n");
+    for (label, regs) in labels {
+        out += &format!("{indent}  {}:\n", yaml_key(label));
+        for r in regs {
+            out += &format!("{indent}    - '{}'\n", r.replace('\'', "''"));
+        }
+    }
+    out
+}
+
+/// Replace the repo_labels block of the repo in the config text, keeping everything else,
+/// including comments, as-is. The block is inserted after the repo_slug, if missing.
+fn replace_block(config: &str, repo_slug: &str, labels: &[(String, Vec<String>)]) -> String {
+    let lines = config.lines().collect::<Vec<_>>();
+    let repo_start = lines
+        .iter()
+        .position(|l| {
+            l.trim_start().trim_start_matches("- ").trim() == format!("repo_slug: {repo_slug}")
+        })
+        .expect("repo_slug not found in config");
+    let repo_end = repo_start
+        + 1
+        + lines[repo_start + 1..]
+            .iter()
+            .position(|l| l.trim_start().starts_with("- repo_slug:"))
+            .unwrap_or(lines.len() - repo_start - 1);
+    let Some(start) = lines[repo_start..repo_end]
+        .iter()
+        .position(|l| l.trim_start().starts_with("repo_labels:"))
+        .map(|p| repo_start + p)
+    else {
+        let slug_line = lines[repo_start];
+        let key_indent = " ".repeat(slug_line.len() - slug_line.trim_start().len() + "- ".len());
+        let mut out = lines[..=repo_start].join("\n") + "\n";
+        out += &render_labels(labels, &key_indent);
+        out += &lines[repo_start + 1..].join("\n");
+        if config.ends_with('\n') && repo_start + 1 < lines.len() {
+            out += "\n";
+        }
+        return out;
+    };
+    let indent = &lines[start][..lines[start].len() - lines[start].trim_start().len()];
+    let end = start
+        + 1
+        + lines[start + 1..]
+            .iter()
+            .position(|l| {
+                !l.trim().is_empty()
+                    && l.len() - l.trim_start().len() <= indent.len()
+                    && !l.trim_start().starts_with("- '")
+            })
+            .unwrap_or(lines.len() - start - 1);
+    let mut out = lines[..start].join("\n") + "\n";
+    out += &render_labels(labels, indent);
+    out += &lines[end..].join("\n");
+    if config.ends_with('\n') {
+        out += "\n";
+    }
+    out
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let contributing =
+        std::fs::read_to_string(&args.contributing_file).expect("contributing file path error");
+    let config_text = std::fs::read_to_string(&args.config_file).expect("config file path error");
+    let config: serde_yaml::Value = serde_yaml::from_str(&config_text).expect("yaml error");
+
+    let repo = config["repositories"]
+        .as_sequence()
+        .expect("yaml error")
+        .iter()
+        .find(|r| r["repo_slug"].as_str() == Some(args.repo_slug.as_str()))
+        .expect("repo_slug not found in config");
+    let mut labels = repo["repo_labels"]
+        .as_mapping()
+        .into_iter()
+        .flatten()
+        .map(|(label, regs)| {
+            (
+                label.as_str().expect("yaml error").to_string(),
+                regs.as_sequence()
+                    .into_iter()
+                    .flatten()
+                    .map(|r| r.as_str().expect("yaml error").to_string())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let prefixes = parse_prefixes(&contributing);
+    if prefixes.is_empty() {
+        panic!("No prefixes found in the contributing file");
+    }
+    println!("Found {} prefixes: {}", prefixes.len(), prefixes.join(", "));
+    let unmapped = map_prefixes(&mut labels, &prefixes, &args.map);
+    for p in &unmapped {
+        println!("Prefix '{p}' does not match any label. Use --map {p}=<Label> to map it.");
+    }
+
+    let new_text = replace_block(&config_text, &args.repo_slug, &labels);
+    if new_text == config_text {
+        println!("The config is up to date.");
+        return;
+    }
+    let temp = tempfile::NamedTempFile::new().expect("tempfile error");
+    std::fs::write(temp.path(), &new_text).expect("tempfile error");
+    // The exit code is 1, because there are differences
+    util::git()
+        .args(["--no-pager", "diff", "--no-index"])
+        .arg(&args.config_file)
+        .arg(temp.path())
+        .status()
+        .expect("git diff error");
+    if args.write {
+        std::fs::write(&args.config_file, new_text).expect("config file write error");
+        println!("Wrote {}", args.config_file.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gen_repo_labels() {
+        let contributing = "# Contributing\n\n### Creating the Pull Request\n\nValid areas as:\n\n  - `consensus` for changes to consensus critical code\n  - `rpc`, `rest` or `zmq` for changes to the RPC, REST or ZMQ APIs\n  - `kernel` for changes to the `bitcoinkernel` library\n\nExamples:\n\n    consensus: Add new opcode\n\n### Other\n\n  - `ignored` for changes\n";
+        let prefixes = parse_prefixes(contributing);
+        assert_eq!(prefixes, vec!["consensus", "rpc", "rest", "zmq", "kernel"]);
+
+        let mut labels = vec![
+            ("Consensus".to_string(), vec!["^consensus:".to_string()]),
+            (
+                "RPC/REST/ZMQ".to_string(),
+                vec!["^rpc:".to_string(), "^rest:".to_string()],
+            ),
+        ];
+        let unmapped = map_prefixes(
+            &mut labels,
+            &prefixes,
+            &[("zmq".to_string(), "RPC/REST/ZMQ".to_string())],
+        );
+        assert_eq!(unmapped, vec!["kernel"]);
+        assert_eq!(labels[1].1, vec!["^rpc:", "^rest:", "^zmq:"]);
+
+        let config = "repositories:\n  - repo_slug: a/b\n    repo_labels:\n      Dummy:\n        - '^dummy:'\n  - repo_slug: bitcoin/bitcoin\n    # comment\n    repo_labels:\n      Old:\n        - '^old:'\n    spam_detection: true\n";
+        assert_eq!(
+            replace_block(config, "bitcoin/bitcoin", &labels[..1]),
+            "repositories:\n  - repo_slug: a/b\n    repo_labels:\n      Dummy:\n        - '^dummy:'\n  - repo_slug: bitcoin/bitcoin\n    # comment\n    repo_labels:\n      Consensus:\n        - '^consensus:'\n    spam_detection: true\n"
+        );
+        // The block of the next repo is left alone, and names are quoted if needed
+        let labels = vec![("RPC: Wallet".to_string(), vec!["^rpc:".to_string()])];
+        assert_eq!(
+            replace_block(config, "a/b", &labels),
+            "repositories:\n  - repo_slug: a/b\n    repo_labels:\n      'RPC: Wallet':\n        - '^rpc:'\n  - repo_slug: bitcoin/bitcoin\n    # comment\n    repo_labels:\n      Old:\n        - '^old:'\n    spam_detection: true\n"
+        );
+        let config = "repositories:\n  - repo_slug: a/b\n    spam_detection: true\n  - repo_slug: bitcoin/bitcoin\n    repo_labels:\n      Old:\n        - '^old:'\n";
+        assert_eq!(
+            replace_block(config, "a/b", &labels),
+            "repositories:\n  - repo_slug: a/b\n    repo_labels:\n      'RPC: Wallet':\n        - '^rpc:'\n    spam_detection: true\n  - repo_slug: bitcoin/bitcoin\n    repo_labels:\n      Old:\n        - '^old:'\n"
+        );
+        assert_eq!(yaml_key("*Wallet"), "'*Wallet'");
+        assert_eq!(yaml_key("Utils/log/libs"), "Utils/log/libs");
+    }
+}
@@ -363,7 +363,7 @@ async fn main() -> octocrab::Result<()> {
         let issues_api = github.issues(owner, repo);
         let pulls_api = github.pulls(owner, repo);
 
-        let label_needs_guix = "DrahtBot Guix build requested";
+        let label_needs_guix = util::LABEL_GUIX_BUILD_REQUESTED;
         let search_fmt = format!(
             "repo:{owner}/{repo} is:open is:pr label:\"{label}\" ",
             owner = owner,
@@ -3,20 +3,32 @@ use std::fs;
 use std::hash::{BuildHasher, Hasher, RandomState};
 use std::path::Path;
 use std::process::Command;
-use util::{LLM_SHARED_PROMPT_DIFF, LLM_TYPOS, make_llm_payload, prepare_raw_diff_for_llm};
+use util::llm::{LlmClient, LlmConfig, LlmProvider, read_llm_examples};
+use util::{LLM_TYPOS, make_llm_messages, prepare_raw_diff_for_llm};
 
 #[derive(Parser)]
 #[command(about = "Scratch script to evaluate LLMs.", long_about = None)]
 struct Cli {
     #[arg(long)]
     open_ai_token: String,
+    /// Replay the labelled examples of a dataset.jsonl collected by the webhook LLM feedback
+    /// feature, instead of checking the diffs in ./inputs.
     #[arg(long)]
-    google_ai_token: String,
+    dataset: Option<std::path::PathBuf>,
 }
 
-fn main() {
+#[tokio::main]
+async fn main() {
     let cli = Cli::parse();
 
+    let open_ai = LlmClient::new(
+        LlmConfig {
+            reasoning_effort: Some("medium".to_string()),
+            ..LlmConfig::new(LlmProvider::OpenAi, "gpt-5.4-mini")
+        },
+        &cli.open_ai_token,
+    );
+
     let inputs = fs::canonicalize("./inputs").expect("folder must exist");
 
     let outputs = format!(
@@ -36,6 +48,11 @@ fn main() {
     fs::create_dir(&outputs).expect("folder must be creatable");
     let outputs = fs::canonicalize(outputs).expect("folder must exist");
 
+    if let Some(dataset) = &cli.dataset {
+        replay_dataset(&open_ai, "open_ai", &outputs, dataset).await;
+        return;
+    }
+
     for entry in fs::read_dir(inputs).expect("folder must exist") {
         let entry = entry.expect("file must exist");
         let file_name = entry
@@ -54,114 +71,57 @@ fn main() {
             prepare_raw_diff_for_llm(&diff)
         );
 
-        //check_google_ai(&cli, &outputs, &file_name, &diff);
-        check_open_ai(&cli, &outputs, &file_name, &diff);
+        check(&open_ai, "open_ai", &outputs, &file_name, &diff).await;
     }
 }
 
-fn check_google_ai(cli: &Cli, outputs: &Path, file_name: &str, diff: &str) {
-    println!("Check {file_name} via google_ai");
-    let payload = serde_json::json!({
-      "systemInstruction": {
-         "parts": [
-           {
-               "text":LLM_SHARED_PROMPT_DIFF
-           },
-         ]
-       },
-       "contents": [
-        {
-          "parts": [
-            {
-              "text": diff
-            }
-          ]
-        },
-        {
-          "parts": [
-            {
-              "text": LLM_TYPOS.prompt()
-            }
-          ]
+/// Send the messages of each example again and write the previous and the new reply side by side,
+/// along with the verdict on the previous reply.
+async fn replay_dataset(llm: &LlmClient, name: &str, outputs: &Path, dataset: &Path) {
+    let examples = read_llm_examples(dataset).expect("Must be able to read dataset");
+    let mut changed = 0;
+    for (i, example) in examples.iter().enumerate() {
+        println!(
+            "Replay example {i} ({kind}, {verdict:?}) via {name}",
+            kind = example.kind,
+            verdict = example.verdict
+        );
+        let val = match llm.chat(&example.exchange.messages).await {
+            Ok(reply) => reply.text,
+            Err(err) => format!("{err}"),
+        };
+        if val.trim() != example.exchange.reply.trim() {
+            changed += 1;
         }
-      ]
-    });
-    let temp = outputs
-        .join("temp_scratch")
-        .to_str()
-        .expect("must be valid utf8")
-        .to_string();
-    fs::write(
-        &temp,
-        serde_json::to_string(&payload).expect("must be valid json"),
-    )
-    .expect("Must be able to write file");
-    let curl_out = Command::new("curl")
-        .arg(format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-lite-preview-06-17:generateContent?key={}"
-            ,cli.google_ai_token))
-        .arg("--fail")
-        .arg("-H")
-        .arg("Content-Type: application/json")
-        .arg("-X")
-        .arg("POST")
-        .arg("-d")
-        .arg(format!("@{}", temp))
-        .output()
-        .expect("curl error");
-    assert!(curl_out.status.success());
-    let response: serde_json::Value =
-        serde_json::from_str(&String::from_utf8(curl_out.stdout).expect("must be valid utf8"))
-            .expect("must be valid json");
-    let val = response["candidates"][0]["content"]["parts"][0]["text"]
-        .as_str()
-        .expect("Content not found");
-    if val.is_empty() {
-        // Could be due to https://discuss.ai.google.dev/t/gemini-2-5-pro-with-empty-response-text/81175/23 or just hitting the output token limit
-        println!("EMPTY:\n{response}");
-    }
-    fs::write(
-        outputs.join(format!("{}.google_ai.dbg", file_name)),
-        format!("{response}"),
-    )
-    .expect("Must be able to write file");
-    fs::write(outputs.join(format!("{}.google_ai.txt", file_name)), val)
+        fs::write(
+            outputs.join(format!("{i}.{kind}.{name}.txt", kind = example.kind)),
+            format!(
+                "url: {url}\nverdict: {verdict:?}\nprevious model: {model}\n\n# Previous reply\n\n{prev}\n\n# New reply\n\n{val}\n",
+                url = example.url,
+                verdict = example.verdict,
+                model = example.exchange.model,
+                prev = example.exchange.reply,
+            ),
+        )
         .expect("Must be able to write file");
+    }
+    println!(
+        "{changed} of {total} replies changed. See {dir}",
+        total = examples.len(),
+        dir = outputs.display()
+    );
 }
 
-fn check_open_ai(cli: &Cli, outputs: &Path, file_name: &str, diff: &str) {
-    println!("Check {file_name} via open_ai");
-    let payload = make_llm_payload(diff, LLM_TYPOS.prompt());
-    let temp = outputs
-        .join("temp_scratch")
-        .to_str()
-        .expect("must be valid utf8")
-        .to_string();
-    fs::write(
-        &temp,
-        serde_json::to_string(&payload).expect("must be valid json"),
-    )
-    .expect("Must be able to write file");
-    let curl_out = Command::new("curl")
-        .arg("--fail")
-        .arg("-X")
-        .arg("POST")
-        .arg("https://api.openai.com/v1/chat/completions")
-        .arg("-H")
-        .arg("Content-Type: application/json")
-        .arg("-H")
-        .arg(format!("Authorization: Bearer {}", cli.open_ai_token))
-        .arg("-d")
-        .arg(format!("@{}", temp))
-        .output()
-        .expect("curl error");
-    assert!(curl_out.status.success());
-    let response: serde_json::Value =
-        serde_json::from_str(&String::from_utf8(curl_out.stdout).expect("must be valid utf8"))
-            .expect("must be valid json");
-    let val = response["choices"][0]["message"]["content"]
-        .as_str()
-        .expect("Content not found");
-    fs::write(outputs.join(format!("{}.open_ai.txt", file_name)), val)
+async fn check(llm: &LlmClient, name: &str, outputs: &Path, file_name: &str, diff: &str) {
+    println!("Check {file_name} via {name}");
+    let val = match llm.chat(&make_llm_messages(diff, LLM_TYPOS.prompt())).await {
+        Ok(reply) => reply.text,
+        Err(err) => {
+            // Could be due to https://discuss.ai.google.dev/t/gemini-2-5-pro-with-empty-response-text/81175/23 or just hitting the output token limit
+            println!("ERROR:\n{err}");
+            format!("{err}")
+        }
+    };
+    fs::write(outputs.join(format!("{file_name}.{name}.txt")), val)
         .expect("Must be able to write file");
 }
@@ -0,0 +1,346 @@
+use clap::Parser;
+
+#[derive(clap::Parser)]
+#[command(about = "Verify the scripted-diff commits of pull requests by re-running their verify script in a sandbox.", long_about = None)]
+struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: util::Slug,
+    /// Only check this pull request number. If not given, all open pull requests are checked.
+    #[arg(long)]
+    pull_num: Option<u64>,
+    /// The local dir used for scratching.
+    #[arg(long)]
+    scratch_dir: std::path::PathBuf,
+    /// The path to the yaml config file.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// The yaml file with the layouts of the metadata comment per repo slug. If not given, the
+    /// builtin util/meta_layouts.yml is used.
+    #[arg(long)]
+    meta_layouts_file: Option<std::path::PathBuf>,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct Config {
+    scripted_diff_heading: String,
+    scripted_diff_description: String,
+    /// The command prefix to run the script without network access. The placeholders
+    /// `{work_tree}` and `{git_dir}` are replaced by the paths for the script, and
+    /// `{shared_git_dir}` by the git dir of the throwaway clone, which holds the objects.
+    sandbox: Vec<String>,
+    timeout_secs: u64,
+}
+
+const SCRIPTED_DIFF_PREFIX: &str = "scripted-diff:";
+
+/// Return the lines between the verify script markers of the commit message, like
+/// test/lint/commit-script-check.sh.
+fn extract_verify_script(message: &str) -> Option<String> {
+    let mut lines = message
+        .lines()
+        .skip_while(|l| l.trim_end() != "-BEGIN VERIFY SCRIPT-")
+        .skip(1);
+    let script = lines
+        .by_ref()
+        .take_while(|l| l.trim_end() != "-END VERIFY SCRIPT-")
+        .collect::<Vec<_>>()
+        .join("\n");
+    if script.trim().is_empty() {
+        return None;
+    }
+    Some(script)
+}
+
+fn init_git(git_dir: &std::path::Path, slug: &util::Slug) {
+    if git_dir.is_dir() {
+        return;
+    }
+    let url = format!("https://github.com/{sl}", sl = slug.str());
+    println!("Clone {url} repo to {dir}", dir = git_dir.display());
+    util::check_call(
+        util::git()
+            .args(["clone", "--quiet", "--no-checkout", &url])
+            .arg(git_dir),
+    );
+    util::chdir(git_dir);
+    util::check_call(util::git().args(["config", "fetch.showForcedUpdates", "false"]));
+    util::check_call(util::git().args(["config", "gc.auto", "0"]));
+}
+
+/// Run the verify script of the commit on its parent in a throwaway clone, and compare the result
+/// with the commit. The clone shares no objects, config, or hooks with the persistent one.
+fn verify_commit(
+    config: &Config,
+    temp_dir: &std::path::Path,
+    commit: &str,
+    message: &str,
+) -> Result<(), String> {
+    let Some(script) = extract_verify_script(message) else {
+        return Err("Missing verify script".to_string());
+    };
+    let scratch = tempfile::TempDir::new_in(temp_dir).expect("tempdir error");
+    let git_dir = scratch.path().join("git");
+    let sandbox_git_dir = scratch.path().join("sandbox_git");
+    let work_tree = scratch.path().join("work");
+    util::check_call(
+        util::git()
+            .args(["clone", "--quiet", "--mirror", "--no-local", "."])
+            .arg(&git_dir),
+    );
+    std::fs::create_dir(&work_tree).expect("tempdir error");
+    // The git dir is kept outside of the work tree and hidden from the script, so that the result
+    // is compared with a git dir that the script could not modify
+    let git = || {
+        let mut cmd = util::git();
+        cmd.arg("--git-dir")
+            .arg(&git_dir)
+            .arg("--work-tree")
+            .arg(&work_tree);
+        cmd
+    };
+    util::check_call(git().args(["config", "core.bare", "false"]));
+    util::check_call(
+        git()
+            .args(["checkout", "--quiet", "--detach"])
+            .arg(format!("{commit}~1")),
+    );
+    // The script gets its own writable git dir, which borrows the objects of the clone, so that it
+    // can use `git mv`
+    util::check_call(
+        util::git()
+            .args(["clone", "--quiet", "--bare", "--shared"])
+            .arg(&git_dir)
+            .arg(&sandbox_git_dir),
+    );
+    util::check_call(util::git().arg("--git-dir").arg(&sandbox_git_dir).args([
+        "config",
+        "core.bare",
+        "false",
+    ]));
+    util::check_call(
+        util::git()
+            .arg("--git-dir")
+            .arg(&sandbox_git_dir)
+            .args(["update-ref", "--no-deref", "HEAD"])
+            .arg(format!("{commit}~1")),
+    );
+    std::fs::copy(git_dir.join("index"), sandbox_git_dir.join("index")).expect("index copy error");
+    let sandbox = config.sandbox.iter().map(|a| {
+        a.replace("{work_tree}", work_tree.to_str().expect("utf8 path"))
+            .replace("{git_dir}", sandbox_git_dir.to_str().expect("utf8 path"))
+            .replace("{shared_git_dir}", git_dir.to_str().expect("utf8 path"))
+    });
+    let status = std::process::Command::new("timeout")
+        .args(["--kill-after=10", &config.timeout_secs.to_string()])
+        .args(sandbox)
+        .args(["bash", "-c", &script])
+        .current_dir(&work_tree)
+        // Only pass the PATH, to not leak any credentials from the environment
+        .env_clear()
+        .envs(std::env::var_os("PATH").map(|p| ("PATH", p)))
+        .status()
+        .expect("sandbox command error");
+    match status.code() {
+        Some(0) => {
+            util::check_call(git().args(["add", "--all"]));
+            let diff = util::check_output(git().args(["diff", "--cached", "--name-only", commit]));
+            if diff.is_empty() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "The result differs from the commit in: {}",
+                    diff.lines()
+                        .map(|f| format!("`{f}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            }
+        }
+        Some(124) | Some(137) => Err(format!(
+            "The script timed out after {}s",
+            config.timeout_secs
+        )),
+        code => Err(format!("The script failed with exit code {code:?}")),
+    }
+}
+
+async fn check_pull(
+    config: &Config,
+    github: &octocrab::Octocrab,
+    args: &Args,
+    temp_dir: &std::path::Path,
+    pull: &octocrab::models::pulls::PullRequest,
+) -> octocrab::Result<()> {
+    let util::Slug { owner, repo } = &args.github_repo;
+    let api_issues = github.issues(owner, repo);
+    let mut cmt = util::get_metadata_sections(
+        github,
+        &api_issues,
+        pull.number,
+        &util::MetaLayout::for_repo(&args.github_repo.str()),
+    )
+    .await?;
+    let head = &pull.head.sha;
+    if cmt
+        .state(util::MetaStateKey::ScriptedDiffHead)
+        .and_then(|h| h.as_str())
+        == Some(head)
+    {
+        println!("... {head} already verified");
+        return Ok(());
+    }
+
+    util::check_call(util::git().args([
+        "fetch",
+        "--quiet",
+        "origin",
+        &format!(
+            "+refs/heads/{b}:refs/remotes/origin/{b}",
+            b = pull.base.ref_field
+        ),
+        &format!(
+            "+refs/pull/{n}/head:refs/remotes/upstream-pull/{n}/head",
+            n = pull.number
+        ),
+    ]));
+    let commits = util::check_output(util::git().args([
+        "rev-list",
+        "--reverse",
+        "--no-merges",
+        &format!("origin/{}..{head}", pull.base.ref_field),
+    ]));
+    let mut results = Vec::new();
+    for commit in commits.lines() {
+        let message = util::check_output(util::git().args(["log", "-1", "--format=%B", commit]));
+        let subject = message.lines().next().unwrap_or_default();
+        if !subject.starts_with(SCRIPTED_DIFF_PREFIX) {
+            continue;
+        }
+        println!("... Verify {commit} {subject}");
+        let result = verify_commit(config, temp_dir, commit, &message);
+        println!("... {result:?}");
+        results.push(format!(
+            "| {short} {subject} | {result} |",
+            short = &commit[..12],
+            subject = subject.replace('|', "\\|"),
+            result = match result {
+                Ok(()) => "✅ Verified".to_string(),
+                Err(e) => format!("❌ {e}"),
+            }
+        ));
+    }
+    if results.is_empty() && !cmt.has_section(&util::IdComment::SecScriptedDiff) {
+        // No scripted-diffs and no section to update
+        return Ok(());
+    }
+
+    cmt.set_state(util::MetaStateKey::ScriptedDiffHead, head.clone().into());
+    let text = if results.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "\n### {hd}\n{desc}\n| Commit | Result |\n| --- | --- |\n{res}\n",
+            hd = config.scripted_diff_heading,
+            desc = config.scripted_diff_description,
+            res = results.join("\n"),
+        )
+    };
+    util::update_metadata_comment(
+        &api_issues,
+        &mut cmt,
+        &text,
+        util::IdComment::SecScriptedDiff,
+        args.dry_run,
+    )
+    .await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> octocrab::Result<()> {
+    let args = Args::parse();
+    if let Some(path) = &args.meta_layouts_file {
+        util::MetaLayout::load(path);
+    }
+
+    let config: Config = serde_yaml::from_reader(
+        std::fs::File::open(&args.config_file).expect("config file path error"),
+    )
+    .expect("yaml error");
+
+    let github = util::get_octocrab(args.github_access_token.clone())?;
+
+    std::fs::create_dir_all(&args.scratch_dir).expect("invalid scratch_dir");
+
+    let git_dir = args
+        .scratch_dir
+        .canonicalize()
+        .expect("invalid scratch_dir")
+        .join(format!(
+            "{}_{}",
+            args.github_repo.owner, args.github_repo.repo
+        ))
+        .join("persist");
+    let temp_dir = git_dir.parent().unwrap().join("temp");
+    std::fs::create_dir_all(&temp_dir).expect("invalid temp_dir");
+
+    init_git(&git_dir, &args.github_repo);
+    util::chdir(&git_dir);
+
+    let util::Slug { owner, repo } = &args.github_repo;
+    let pulls_api = github.pulls(owner, repo);
+    let pulls = match args.pull_num {
+        Some(num) => vec![pulls_api.get(num).await?],
+        None => {
+            github
+                .all_pages(
+                    pulls_api
+                        .list()
+                        .state(octocrab::params::State::Open)
+                        .send()
+                        .await?,
+                )
+                .await?
+        }
+    };
+    for pull in &pulls {
+        println!(
+            "Check scripted-diffs of {sl}#{num}",
+            sl = args.github_repo.str(),
+            num = pull.number
+        );
+        if let Err(err) = check_pull(&config, &github, &args, &temp_dir, pull).await {
+            println!("... ERROR when checking {num}: {err:?}", num = pull.number);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_verify_script() {
+        let msg = "scripted-diff: Rename foo to bar\n\n-BEGIN VERIFY SCRIPT-\nsed -i 's/foo/bar/g' $(git grep -l foo)\n-END VERIFY SCRIPT-\n";
+        assert_eq!(
+            extract_verify_script(msg).as_deref(),
+            Some("sed -i 's/foo/bar/g' $(git grep -l foo)")
+        );
+        assert_eq!(
+            extract_verify_script("scripted-diff: Rename\n\nNo script"),
+            None
+        );
+        assert_eq!(
+            extract_verify_script("-BEGIN VERIFY SCRIPT-\n-END VERIFY SCRIPT-"),
+            None
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use clap::Parser;
+use lazy_static::lazy_static;
+use regex::Regex;
+use util::KnownFailure;
+
+#[derive(clap::Parser)]
+#[command(about = "\
+Seed the known intermittent CI failures for the webhook ci_known_failures config:
+* Add open issues with one of the labels, whose title names a failing test.\n\
+* Drop entries whose issue was closed.\n\
+* Keep all other entries, including manual edits.\n\
+", long_about = None)]
+struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: util::Slug,
+    /// The labels of the issues to seed from.
+    #[arg(long, default_values_t = util::KNOWN_FAILURE_LABELS.map(|l| l.to_string()))]
+    label: Vec<String>,
+    /// The yaml file with the known failures.
+    #[arg(long)]
+    file: std::path::PathBuf,
+    /// Print the known failures instead of writing the file.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+lazy_static! {
+    static ref TEST: Regex = Regex::new(r"\b(\w+\.py|\w+_tests(?:/\w+)?)\b").unwrap();
+    static ref INTERMITTENT: Regex =
+        Regex::new(r"(?i)intermittent|flaky|sporadic|timeout|fail").unwrap();
+    static ref ERROR: Regex =
+        Regex::new(r"\w+(?:Error|Exception): .+|check .+ has failed.*").unwrap();
+    static ref NUMBER: Regex = Regex::new(r"\d+").unwrap();
+}
+
+/// Return the known failure for the issue, if the title names a failing test. The error is taken
+/// from the first Python exception or Boost.Test check in the description, with the numbers
+/// replaced by wildcards.
+fn parse_issue(title: &str, body: &str, issue: &str) -> Option<KnownFailure> {
+    if !INTERMITTENT.is_match(title) {
+        return None;
+    }
+    let test = TEST.captures(title)?[1].to_string();
+    let error = ERROR.find(body).map(|e| {
+        let e = e.as_str().trim().chars().take(100).collect::<String>();
+        NUMBER.replace_all(&regex::escape(&e), r"\d+").into_owned()
+    });
+    Some(KnownFailure {
+        test,
+        error,
+        issue: issue.to_string(),
+        title: title.to_string(),
+    })
+}
+
+#[tokio::main]
+async fn main() -> octocrab::Result<()> {
+    let args = Args::parse();
+
+    let github = util::get_octocrab(args.github_access_token.clone())?;
+
+    let mut known: Vec<KnownFailure> = if args.file.exists() {
+        serde_yaml::from_reader(std::fs::File::open(&args.file).expect("file path error"))
+            .expect("yaml error")
+    } else {
+        Vec::new()
+    };
+
+    let mut open = Vec::new();
+    for label in &args.label {
+        let items = github
+            .all_pages(
+                github
+                    .search()
+                    .issues_and_pull_requests(&format!(
+                        "repo:{} is:issue is:open label:\"{label}\"",
+                        args.github_repo.str()
+                    ))
+                    .send()
+                    .await?,
+            )
+            .await?;
+        println!("Open issues with label '{label}': {}", items.len());
+        open.extend(items);
+    }
+
+    let mut kept = Vec::new();
+    for k in known.drain(..) {
+        if open.iter().any(|i| i.html_url.as_str() == k.issue) {
+            kept.push(k);
+            continue;
+        }
+        let route = format!(
+            "/repos/{}",
+            k.issue
+                .strip_prefix("https://github.com/")
+                .expect("issue url format error")
+        );
+        let issue = github.get::<serde_json::Value, _, ()>(route, None).await?;
+        if issue["state"] == "open" {
+            kept.push(k);
+        } else {
+            println!("Drop {} (closed)", k.issue);
+        }
+    }
+    known = kept;
+
+    for issue in &open {
+        let url = issue.html_url.as_str();
+        if known.iter().any(|k| k.issue == url) {
+            continue;
+        }
+        if let Some(k) = parse_issue(&issue.title, issue.body.as_deref().unwrap_or_default(), url) {
+            println!("Add {} ({})", k.issue, k.test);
+            known.push(k);
+        }
+    }
+
+    let text = format!(
+        "# Seeded by seed_known_failures. Manual edits are kept.\n{}",
+        serde_yaml::to_string(&known).expect("yaml error")
+    );
+    if args.dry_run {
+        println!("{text}");
+    } else {
+        std::fs::write(&args.file, text).expect("file write error");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_issue() {
+        let url = "https://github.com/bitcoin/bitcoin/issues/1";
+        assert_eq!(
+            parse_issue(
+                "ci: intermittent failure in mempool_limit.py",
+                "```\n AssertionError: not(6 == 5)\n```",
+                url
+            ),
+            Some(KnownFailure {
+                test: "mempool_limit.py".to_string(),
+                error: Some(r"AssertionError: not\(\d+ == \d+\)".to_string()),
+                issue: url.to_string(),
+                title: "ci: intermittent failure in mempool_limit.py".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_issue("net_tests/cnode_simple_test fails sometimes", "", url).map(|k| k.test),
+            Some("net_tests/cnode_simple_test".to_string())
+        );
+        assert_eq!(
+            parse_issue("test: Add coverage for wallet_basic.py", "", url),
+            None
+        );
+        assert_eq!(
+            parse_issue("ci: intermittent failure on windows", "", url),
+            None
+        );
+    }
+}
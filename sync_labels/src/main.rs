@@ -0,0 +1,418 @@
+use clap::Parser;
+
+#[derive(clap::Parser)]
+#[command(about = "\
+Create and update the labels of the label catalogue in GitHub repos:
+* Check that every label used by the tools is in the catalogue.\n\
+* Create missing labels and update the color and description of existing ones.\n\
+* Report labels that are not in the catalogue. They are never deleted.\n\
+", long_about = None)]
+struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// The repo slugs of the remotes on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: Vec<util::Slug>,
+    /// The path to the yaml label catalogue.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// The webhook_features yaml config, to check the labels used in it.
+    #[arg(long)]
+    webhook_config_file: Option<std::path::PathBuf>,
+    /// The backport yaml config, to check the labels used in it. The branches are taken from
+    /// backport_branch_labels in the webhook_features config.
+    #[arg(long)]
+    backport_config_file: Option<std::path::PathBuf>,
+    /// The stale yaml config, to check the labels used in it.
+    #[arg(long)]
+    stale_config_file: Option<std::path::PathBuf>,
+    /// Only check the catalogue, without calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    check_only: bool,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct Label {
+    name: String,
+    /// The hex color without the leading #.
+    color: String,
+    #[serde(default)]
+    description: String,
+    /// The repo slugs to create the label in. If empty, it is created in all repos.
+    #[serde(default)]
+    repos: Vec<String>,
+}
+
+impl Label {
+    fn in_repo(&self, slug: &str) -> bool {
+        self.repos.is_empty() || self.repos.iter().any(|r| r == slug)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct Config {
+    labels: Vec<Label>,
+}
+
+/// A label name used by a tool, along with the repo slug it is used in (None for all repos) and
+/// where it comes from.
+struct UsedLabel {
+    repo: Option<String>,
+    name: String,
+    source: String,
+}
+
+/// Return the repo slugs and release branches of backport_branch_labels in the webhook_features
+/// config.
+fn backport_branches(config: &serde_yaml::Value) -> Vec<(String, String)> {
+    let mut branches = Vec::new();
+    for repo in config["repositories"].as_sequence().into_iter().flatten() {
+        let slug = repo["repo_slug"].as_str().unwrap_or_default();
+        for branch in repo["backport_branch_labels"]
+            .as_mapping()
+            .into_iter()
+            .flatten()
+            .filter_map(|(b, _)| b.as_str())
+        {
+            branches.push((slug.to_string(), branch.to_string()));
+        }
+    }
+    branches
+}
+
+/// Collect the label names used in the webhook_features config.
+fn webhook_labels(config: &serde_yaml::Value) -> Vec<UsedLabel> {
+    let mut used = Vec::new();
+    for repo in config["repositories"].as_sequence().into_iter().flatten() {
+        let slug = repo["repo_slug"].as_str().unwrap_or_default().to_string();
+        let mut push = |name: &str, key: &str| {
+            used.push(UsedLabel {
+                repo: Some(slug.clone()),
+                name: name.to_string(),
+                source: format!("webhook_features {slug} {key}"),
+            })
+        };
+        if let Some(name) = repo["backport_label"].as_str() {
+            push(name, "backport_label");
+        }
+        for name in repo["backport_branch_labels"]
+            .as_mapping()
+            .into_iter()
+            .flatten()
+        {
+            push(
+                name.1.as_str().unwrap_or_default(),
+                "backport_branch_labels",
+            );
+        }
+        for key in ["repo_labels", "repo_label_paths"] {
+            for name in repo[key].as_mapping().into_iter().flatten() {
+                push(name.0.as_str().unwrap_or_default(), key);
+            }
+        }
+        for name in repo["repo_label_priority"]
+            .as_sequence()
+            .into_iter()
+            .flatten()
+        {
+            push(name.as_str().unwrap_or_default(), "repo_label_priority");
+        }
+        if let Some(name) = repo["commit_hygiene"]["needs_squash_label"].as_str() {
+            push(name, "commit_hygiene");
+        }
+        if let Some(label) = repo["needs_backport_label"].as_str() {
+            for branch in repo["backport_branch_labels"]
+                .as_mapping()
+                .into_iter()
+                .flatten()
+                .filter_map(|(b, _)| b.as_str())
+            {
+                push(&label.replace("{branch}", branch), "needs_backport_label");
+            }
+        }
+        if !repo["ci_known_failures"].is_null() {
+            for name in util::KNOWN_FAILURE_LABELS {
+                push(name, "ci_known_failures (seed_known_failures)");
+            }
+        }
+    }
+    used
+}
+
+/// Collect the label names used in the backport config, for each of the repos and branches.
+fn backport_labels(config: &serde_yaml::Value, branches: &[(String, String)]) -> Vec<UsedLabel> {
+    let Some(label) = config["needs_backport_label"].as_str() else {
+        return Vec::new();
+    };
+    branches
+        .iter()
+        .map(|(slug, branch)| UsedLabel {
+            repo: Some(slug.clone()),
+            name: label.replace("{branch}", branch),
+            source: "backport needs_backport_label".to_string(),
+        })
+        .collect()
+}
+
+/// Collect the label names used in the stale config.
+fn stale_labels(config: &serde_yaml::Value) -> Vec<UsedLabel> {
+    ["needs_rebase_label", "ci_failed_label"]
+        .into_iter()
+        .filter_map(|key| {
+            Some(UsedLabel {
+                repo: None,
+                name: config[key].as_str()?.to_string(),
+                source: format!("stale {key}"),
+            })
+        })
+        .collect()
+}
+
+/// Return the errors of the catalogue for the given repos.
+fn check_catalogue(config: &Config, used: &[UsedLabel], repos: &[String]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (i, l) in config.labels.iter().enumerate() {
+        if l.color.len() != 6 || !l.color.bytes().all(|b| b.is_ascii_hexdigit()) {
+            errors.push(format!("{}: Invalid color '{}'", l.name, l.color));
+        }
+        if config.labels[..i].iter().any(|o| o.name == l.name) {
+            errors.push(format!("{}: Duplicate label", l.name));
+        }
+    }
+    for u in used {
+        for repo in repos
+            .iter()
+            .filter(|r| u.repo.as_ref().is_none_or(|u| u == *r))
+        {
+            if !config
+                .labels
+                .iter()
+                .any(|l| l.name == u.name && l.in_repo(repo))
+            {
+                errors.push(format!(
+                    "{}: Missing in the catalogue for {repo} (used in {})",
+                    u.name, u.source
+                ));
+            }
+        }
+    }
+    errors
+}
+
+fn read_yaml<T: serde::de::DeserializeOwned>(path: &std::path::Path) -> T {
+    serde_yaml::from_reader(std::fs::File::open(path).expect("config file path error"))
+        .expect("yaml error")
+}
+
+/// Percent-encode the label name for use in an url path.
+fn encode_path(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+async fn sync_repo(
+    github: &octocrab::Octocrab,
+    config: &Config,
+    slug: &util::Slug,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    let sl = slug.str();
+    println!("Sync labels of {sl} ...");
+    let existing = github
+        .all_pages(
+            github
+                .get::<octocrab::Page<serde_json::Value>, _, _>(
+                    format!("/repos/{sl}/labels"),
+                    Some(&serde_json::json!({ "per_page": 100 })),
+                )
+                .await?,
+        )
+        .await?;
+    for label in config.labels.iter().filter(|l| l.in_repo(&sl)) {
+        let body = serde_json::json!({
+            "name": label.name,
+            "color": label.color,
+            "description": label.description,
+        });
+        // Label names are case-insensitive on GitHub
+        match existing.iter().find(|e| {
+            e["name"]
+                .as_str()
+                .is_some_and(|n| n.eq_ignore_ascii_case(&label.name))
+        }) {
+            None => {
+                println!("... create label '{}'", label.name);
+                if !dry_run {
+                    github
+                        .post::<_, serde_json::Value>(format!("/repos/{sl}/labels"), Some(&body))
+                        .await?;
+                }
+            }
+            Some(e) => {
+                let name = e["name"].as_str().unwrap_or_default();
+                if name == label.name
+                    && e["color"]
+                        .as_str()
+                        .is_some_and(|c| c.eq_ignore_ascii_case(&label.color))
+                    && e["description"].as_str().unwrap_or_default() == label.description
+                {
+                    continue;
+                }
+                println!("... update label '{name}'");
+                if !dry_run {
+                    github
+                        .patch::<serde_json::Value, _, _>(
+                            format!("/repos/{sl}/labels/{}", encode_path(name)),
+                            Some(&serde_json::json!({
+                                "new_name": label.name,
+                                "color": label.color,
+                                "description": label.description,
+                            })),
+                        )
+                        .await?;
+                }
+            }
+        }
+    }
+    for e in &existing {
+        let name = e["name"].as_str().unwrap_or_default();
+        if !config
+            .labels
+            .iter()
+            .any(|l| l.in_repo(&sl) && l.name.eq_ignore_ascii_case(name))
+        {
+            println!("... label '{name}' is not in the catalogue");
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> octocrab::Result<()> {
+    let args = Args::parse();
+
+    let config: Config = read_yaml(&args.config_file);
+
+    let mut used = util::HARDCODED_LABELS
+        .iter()
+        .map(|name| UsedLabel {
+            repo: None,
+            name: name.to_string(),
+            source: "hardcoded".to_string(),
+        })
+        .collect::<Vec<_>>();
+    let webhook_config = args
+        .webhook_config_file
+        .as_ref()
+        .map(|path| read_yaml::<serde_yaml::Value>(path));
+    if let Some(config) = &webhook_config {
+        used.extend(webhook_labels(config));
+    }
+    if let Some(path) = &args.backport_config_file {
+        let branches = webhook_config
+            .as_ref()
+            .map(backport_branches)
+            .unwrap_or_default();
+        used.extend(backport_labels(&read_yaml(path), &branches));
+    }
+    if let Some(path) = &args.stale_config_file {
+        used.extend(stale_labels(&read_yaml(path)));
+    }
+    let errors = check_catalogue(
+        &config,
+        &used,
+        &args.github_repo.iter().map(|s| s.str()).collect::<Vec<_>>(),
+    );
+    if !errors.is_empty() {
+        panic!("Label catalogue errors:\n{}", errors.join("\n"));
+    }
+    println!("Label catalogue ok.");
+    if args.check_only {
+        return Ok(());
+    }
+
+    let github = util::get_octocrab(args.github_access_token)?;
+    for slug in &args.github_repo {
+        sync_repo(&github, &config, slug, args.dry_run).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_catalogue() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+labels:
+  - name: CI failed
+    color: "d93f0b"
+  - name: Wallet
+    color: "fbca04"
+    repos:
+      - bitcoin/bitcoin
+  - name: Wallet
+    color: "red"
+"#,
+        )
+        .unwrap();
+        let webhook = serde_yaml::from_str(
+            r#"
+repositories:
+  - repo_slug: bitcoin/bitcoin
+    backport_label: Backport
+    backport_branch_labels:
+      '30.x': Backport 30.x
+    needs_backport_label: Needs backport ({branch})
+    ci_known_failures:
+      file: ./ci_known_failures/bitcoin.yml
+    repo_labels:
+      Wallet:
+        - '^wallet:'
+  - repo_slug: bitcoin-core/gui
+    backport_label: null
+    repo_labels:
+"#,
+        )
+        .unwrap();
+        let mut used = webhook_labels(&webhook);
+        used.extend(backport_labels(
+            &serde_yaml::from_str("needs_backport_label: Needs backport ({branch})").unwrap(),
+            &backport_branches(&webhook),
+        ));
+        used.push(UsedLabel {
+            repo: None,
+            name: "CI failed".to_string(),
+            source: "hardcoded".to_string(),
+        });
+        assert_eq!(
+            check_catalogue(
+                &config,
+                &used,
+                &["bitcoin/bitcoin".to_string(), "bitcoin-core/gui".to_string()]
+            ),
+            vec![
+                "Wallet: Invalid color 'red'",
+                "Wallet: Duplicate label",
+                "Backport: Missing in the catalogue for bitcoin/bitcoin (used in webhook_features bitcoin/bitcoin backport_label)",
+                "Backport 30.x: Missing in the catalogue for bitcoin/bitcoin (used in webhook_features bitcoin/bitcoin backport_branch_labels)",
+                "Needs backport (30.x): Missing in the catalogue for bitcoin/bitcoin (used in webhook_features bitcoin/bitcoin needs_backport_label)",
+                "Tests: Missing in the catalogue for bitcoin/bitcoin (used in webhook_features bitcoin/bitcoin ci_known_failures (seed_known_failures))",
+                "Needs backport (30.x): Missing in the catalogue for bitcoin/bitcoin (used in backport needs_backport_label)",
+            ]
+        );
+        assert_eq!(encode_path("RPC/REST/ZMQ"), "RPC%2FREST%2FZMQ");
+    }
+}
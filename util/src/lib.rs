@@ -1,3 +1,7 @@
+pub mod llm;
+
+use std::borrow::Cow;
+
 #[derive(Clone)]
 pub struct Slug {
     pub owner: String,
@@ -27,6 +31,14 @@ impl std::str::FromStr for Slug {
     }
 }
 
+/// Labels with a fixed name, used by the tools.
+pub const LABEL_CI_FAILED: &str = "CI failed";
+pub const LABEL_GUIX_BUILD_REQUESTED: &str = "DrahtBot Guix build requested";
+/// All of the above, to check that they exist in the label catalogue.
+pub const HARDCODED_LABELS: [&str; 2] = [LABEL_CI_FAILED, LABEL_GUIX_BUILD_REQUESTED];
+/// The default labels of the issues that seed_known_failures reads.
+pub const KNOWN_FAILURE_LABELS: [&str; 2] = [LABEL_CI_FAILED, "Tests"];
+
 #[cfg(feature = "github")]
 pub fn get_octocrab(token: Option<String>) -> octocrab::Result<octocrab::Octocrab> {
     let build = octocrab::Octocrab::builder();
@@ -38,18 +50,26 @@ pub fn get_octocrab(token: Option<String>) -> octocrab::Result<octocrab::Octocra
 }
 
 #[cfg(feature = "github")]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum IdComment {
     NeedsRebase,
     CiFailed,
     InactiveRebase,
     InactiveCi,
     InactiveStale,
+    BackportFailed,
     Metadata, // The "root" section
     SecCodeCoverage,
     SecConflicts,
     SecCoverage,
     SecReviews,
     SecLmCheck,
+    SecOverview,
+    SecCommitChecks,
+    SecScriptedDiff,
+    SecCommitHygiene,
+    SecReviewerSuggestions,
+    SecBackports,
 }
 
 #[cfg(feature = "github")]
@@ -61,20 +81,160 @@ impl IdComment {
             Self::InactiveRebase => "<!--13523179cfe9479db18ec6c5d236f789-->",
             Self::InactiveCi => "<!--2e250dc3d92b2c9115b66051148d6e47-->",
             Self::InactiveStale => "<!--8ac04cdde196e94527acabf64b896448-->",
+            Self::BackportFailed => "<!--7c1e5b9a3d0f4e62a8b4c6d2f1e093a7-->",
             Self::Metadata => "<!--e57a25ab6845829454e8d69fc972939a-->",
             Self::SecCodeCoverage => "<!--006a51241073e994b41acfe9ec718e94-->",
             Self::SecConflicts => "<!--174a7506f384e20aa4161008e828411d-->",
             Self::SecCoverage => "<!--2502f1a698b3751726fa55edcda76cd3-->",
             Self::SecReviews => "<!--021abf342d371248e50ceaed478a90ca-->",
             Self::SecLmCheck => "<!--5faf32d7da4f0f540f40219e4f7537a3-->",
+            Self::SecOverview => "<!--f132e3e7abf07c6aab141f6600ce681c-->",
+            Self::SecCommitChecks => "<!--8e0b2f6c4d1a49e3b5c7a9d2e4f60813-->",
+            Self::SecScriptedDiff => "<!--2b7d94e1c05f4a8693e6d1f0a7c3b852-->",
+            Self::SecCommitHygiene => "<!--c4a61e0d7f3b48e29d5a0b6c8e1f7a34-->",
+            Self::SecReviewerSuggestions => "<!--5d3e8a1f9b2c4607a1e4f8d0c6b39e72-->",
+            Self::SecBackports => "<!--a8f3c2d6e9174b05b2d1e7f4c0a95b36-->",
+        }
+    }
+
+    /// The sections of the metadata comment in their default order, along with the name used to
+    /// refer to them in config files.
+    pub fn meta_sections() -> Vec<(Self, &'static str)> {
+        vec![
+            (Self::SecCodeCoverage, "code_coverage"),
+            (Self::SecReviews, "reviews"),
+            (Self::SecConflicts, "conflicts"),
+            (Self::SecCoverage, "coverage"),
+            (Self::SecLmCheck, "llm_linter"),
+            (Self::SecOverview, "overview"),
+            (Self::SecCommitChecks, "commit_checks"),
+            (Self::SecScriptedDiff, "scripted_diff"),
+            (Self::SecCommitHygiene, "commit_hygiene"),
+            (Self::SecReviewerSuggestions, "reviewer_suggestions"),
+            (Self::SecBackports, "backports"),
+        ]
+    }
+}
+
+/// The per-repo layout of the metadata comment.
+#[cfg(feature = "github")]
+#[derive(serde::Deserialize, Clone)]
+pub struct MetaLayout {
+    /// The description below the root section id.
+    #[serde(default = "MetaLayout::default_description")]
+    pub description: String,
+    /// Section names in the order they should appear. Sections not listed follow in their default
+    /// order.
+    #[serde(default)]
+    pub order: Vec<String>,
+    /// Section names that should be rendered collapsed in a <details> block.
+    #[serde(default)]
+    pub collapsed: Vec<String>,
+}
+
+#[cfg(feature = "github")]
+impl Default for MetaLayout {
+    fn default() -> Self {
+        Self {
+            description: Self::default_description(),
+            order: Vec::new(),
+            collapsed: Vec::new(),
+        }
+    }
+}
+
+/// The builtin layouts of the metadata comment per repo slug.
+#[cfg(feature = "github")]
+const META_LAYOUTS_BUILTIN: &str = include_str!("../meta_layouts.yml");
+
+/// The layouts of the metadata comment per repo slug, parsed once.
+#[cfg(feature = "github")]
+static META_LAYOUTS: std::sync::OnceLock<std::collections::HashMap<String, MetaLayout>> =
+    std::sync::OnceLock::new();
+
+#[cfg(feature = "github")]
+impl MetaLayout {
+    /// Load the layouts from the file, instead of the builtin meta_layouts.yml. Must be called at
+    /// startup, before the first call to for_repo.
+    pub fn load(path: &std::path::Path) {
+        let layouts = serde_yaml::from_reader(
+            std::fs::File::open(path).expect("meta layouts file path error"),
+        )
+        .expect("meta layouts file format error");
+        if META_LAYOUTS.set(layouts).is_err() {
+            panic!("meta layouts must be loaded before their first use");
+        }
+    }
+
+    /// Return the layout of the repo, or the default one.
+    pub fn for_repo(repo_slug: &str) -> Self {
+        META_LAYOUTS
+            .get_or_init(|| {
+                serde_yaml::from_str(META_LAYOUTS_BUILTIN).expect("meta_layouts.yml format error")
+            })
+            .get(repo_slug)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn default_description() -> String {
+        "The following sections might be updated with supplementary metadata relevant to reviewers and maintainers.".to_string()
+    }
+
+    /// Sort key of the section, which starts with its id.
+    fn sort_key(&self, section: &str) -> (usize, usize) {
+        let all = IdComment::meta_sections();
+        match all.iter().position(|(id, _)| section.starts_with(id.str())) {
+            Some(default_pos) => (
+                self.order
+                    .iter()
+                    .position(|n| n == all[default_pos].1)
+                    .unwrap_or(self.order.len()),
+                default_pos,
+            ),
+            // Unknown sections go last
+            None => (self.order.len(), all.len()),
         }
     }
+
+    /// Render the section text, which usually starts with a `### heading` line.
+    fn render(&self, id: IdComment, text: &str) -> String {
+        let collapsed = IdComment::meta_sections()
+            .into_iter()
+            .find(|(i, _)| *i == id)
+            .is_some_and(|(_, name)| self.collapsed.iter().any(|c| c == name));
+        if !collapsed || text.trim().is_empty() {
+            return text.to_string();
+        }
+        let text = text.trim_start_matches('\n');
+        let (summary, body) = match text.strip_prefix("### ") {
+            Some(rest) => rest.split_once('\n').unwrap_or((rest, "")),
+            None => ("Details", text),
+        };
+        format!(
+            "\n<details><summary>{summary}</summary>\n\n{body}\n</details>\n",
+            summary = summary.trim(),
+            body = body.trim_matches('\n'),
+        )
+    }
 }
 
 pub fn git() -> std::process::Command {
     std::process::Command::new("git")
 }
 
+/// Return the stdout of the command, or an error with its stderr.
+pub fn git_output(cmd: &mut std::process::Command) -> std::io::Result<String> {
+    let out = cmd.output()?;
+    if !out.status.success() {
+        return Err(std::io::Error::other(format!(
+            "git error: {}",
+            String::from_utf8_lossy(&out.stderr)
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
 pub fn check_call(cmd: &mut std::process::Command) {
     let status = cmd.status().expect("command error");
     assert!(status.success());
@@ -98,6 +258,39 @@ pub fn chdir(p: &std::path::Path) {
     std::env::set_current_dir(p).expect("chdir error")
 }
 
+/// Clone the repos into a single git dir, if it does not exist, and set up the remotes to fetch
+/// their pull requests.
+pub fn init_monotree(monotree_dir: &std::path::Path, repos: &[Slug]) {
+    use std::io::Write;
+    if monotree_dir.is_dir() {
+        return;
+    }
+    for sl in repos {
+        let sl = sl.str();
+        let url = format!("https://github.com/{sl}");
+        println!("Clone {url} repo to {dir}", dir = monotree_dir.display());
+        if !monotree_dir.is_dir() {
+            check_call(git().args(["clone", "--quiet", &url]).arg(monotree_dir));
+        }
+        println!("Set git metadata");
+        chdir(monotree_dir);
+        {
+            let err = "git config file error";
+            let mut f = std::fs::OpenOptions::new()
+                .append(true)
+                .open(monotree_dir.join(".git").join("config"))
+                .expect(err);
+            writeln!(f, "[remote \"con_pull_ref/{sl}\"]").expect(err);
+            writeln!(f, "    url = {url}").expect(err);
+            writeln!(f, "    fetch = +refs/pull/*:refs/remotes/upstream-pull/*").expect(err);
+        }
+        check_call(git().args(["config", "fetch.showForcedUpdates", "false"]));
+        check_call(git().args(["config", "user.email", "no@ne.nl"]));
+        check_call(git().args(["config", "user.name", "none"]));
+        check_call(git().args(["config", "gc.auto", "0"]));
+    }
+}
+
 /// Normalize a git diff for LLM consumption by dropping removed lines and rewriting hunk headers.
 pub fn prepare_raw_diff_for_llm(diff: &str) -> String {
     diff.lines()
@@ -113,22 +306,315 @@ pub fn prepare_raw_diff_for_llm(diff: &str) -> String {
         .join("\n")
 }
 
+/// Split the diff of one file into hunks, so that each hunk has at most `max_chars`, if possible.
+/// Each returned hunk is prefixed with the file header. Hunks that are too large are split into
+/// several hunks with adjusted hunk headers.
+fn split_file_diff(file_diff: &str, max_chars: usize) -> Vec<String> {
+    let (header, hunks) = match file_diff.find("\n@@ ") {
+        Some(i) => file_diff.split_at(i + 1),
+        None => return vec![file_diff.to_string()],
+    };
+    let mut result = Vec::new();
+    for hunk in hunks
+        .split_inclusive('\n')
+        .fold(Vec::<String>::new(), |mut acc, line| {
+            if line.starts_with("@@ ") || acc.is_empty() {
+                acc.push(String::new());
+            }
+            acc.last_mut().unwrap().push_str(line);
+            acc
+        })
+    {
+        if header.len() + hunk.len() <= max_chars {
+            result.push(format!("{header}{hunk}"));
+            continue;
+        }
+        // Format: @@ -a,b +c,d @@
+        let mut lines = hunk.split_inclusive('\n');
+        let hunk_header = lines.next().unwrap_or_default();
+        let start = |prefix: char| -> u64 {
+            hunk_header
+                .split(' ')
+                .find_map(|r| r.strip_prefix(prefix))
+                .and_then(|r| r.split(',').next())
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0)
+        };
+        let (mut old_line, mut new_line) = (start('-'), start('+'));
+        let mut part = String::new();
+        let mut part_start = (old_line, new_line);
+        for line in lines {
+            if !part.is_empty() && header.len() + part.len() + line.len() > max_chars {
+                result.push(format!(
+                    "{header}@@ -{} +{} @@\n{part}",
+                    part_start.0, part_start.1
+                ));
+                part.clear();
+                part_start = (old_line, new_line);
+            }
+            part.push_str(line);
+            match line.chars().next() {
+                Some('+') => new_line += 1,
+                Some('-') => old_line += 1,
+                Some(' ') => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                _ => {}
+            }
+        }
+        if !part.is_empty() {
+            result.push(format!(
+                "{header}@@ -{} +{} @@\n{part}",
+                part_start.0, part_start.1
+            ));
+        }
+    }
+    result
+}
+
+/// Split a git diff into chunks of at most `max_chars`, if possible. Files are kept together in a
+/// chunk, unless they are too large, in which case they are split by hunk.
+pub fn chunk_diff(diff: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::<String>::new();
+    let mut current = String::new();
+    for (_, file_diff) in split_diff_files(diff) {
+        let parts = if file_diff.len() <= max_chars {
+            vec![file_diff.to_string()]
+        } else {
+            split_file_diff(file_diff, max_chars)
+        };
+        for part in parts {
+            if !current.is_empty() && current.len() + part.len() > max_chars {
+                chunks.push(std::mem::take(&mut current));
+            }
+            current.push_str(&part);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// A line on the right (new) side of a git diff, which can be commented on in a review.
+#[derive(Debug, PartialEq)]
+pub struct DiffLine {
+    pub added: bool,
+    pub text: String,
+}
+
+/// Walk the lines of a git diff and call `f` with the file path and new line number of every line
+/// on the right side of the diff, or `None` for all other lines.
+fn walk_diff<'a>(diff: &'a str, mut f: impl FnMut(&'a str, Option<(&'a str, u64)>)) {
+    let mut path = None;
+    let mut new_line = 0;
+    let mut in_hunk = false;
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            path = None;
+            in_hunk = false;
+        } else if !in_hunk && line.starts_with("+++ ") {
+            path = line.strip_prefix("+++ b/");
+        } else if line.starts_with("@@ ") {
+            // Format: @@ -a,b +c,d @@
+            new_line = line
+                .split(' ')
+                .find_map(|r| r.strip_prefix('+'))
+                .and_then(|r| r.split(',').next())
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            in_hunk = true;
+        } else if in_hunk && (line.starts_with('+') || line.starts_with(' ')) {
+            if let Some(path) = path {
+                f(line, Some((path, new_line)));
+                new_line += 1;
+                continue;
+            }
+        }
+        f(line, None);
+    }
+}
+
+/// Return the lines on the right side of a git diff by file path and line number.
+pub fn parse_diff_right_side(
+    diff: &str,
+) -> std::collections::BTreeMap<String, std::collections::BTreeMap<u64, DiffLine>> {
+    let mut files = std::collections::BTreeMap::<String, std::collections::BTreeMap<_, _>>::new();
+    walk_diff(diff, |line, pos| {
+        if let Some((path, num)) = pos {
+            files.entry(path.to_string()).or_default().insert(
+                num,
+                DiffLine {
+                    added: line.starts_with('+'),
+                    text: line[1..].to_string(),
+                },
+            );
+        }
+    });
+    files
+}
+
+/// Like prepare_raw_diff_for_llm, but prefix each line with its line number in the new file, so
+/// that the LLM can refer to it.
+pub fn prepare_numbered_diff_for_llm(diff: &str) -> String {
+    let mut out = Vec::new();
+    walk_diff(diff, |line, pos| match pos {
+        Some((_, num)) => out.push(format!("{num} {line}")),
+        None if line.starts_with('-') => {}
+        None if line.starts_with('@') => out.push("@@ (hunk header) @@".to_string()),
+        None => out.push(line.to_string()),
+    });
+    out.join("\n")
+}
+
+/// A single finding of an LLM check on a line in the new version of a file.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct LlmFinding {
+    pub file: String,
+    pub line: u64,
+    pub message: String,
+}
+
+/// Output format that replaces the free-form output format of an LlmCheck prompt.
+pub const LLM_PROMPT_FINDINGS_FORMAT: &str = r#"
+# Output Format
+
+Output only a JSON array, without markdown code fences. Each element is one finding:
+
+{"file": "path/of/the/file", "line": 123, "message": "brief explanation and suggestion"}
+
+- "file" is the path of the file as given in the `+++ b/` line of the diff.
+- "line" is the number at the start of the diff line that the finding refers to.
+
+If none are found, output: []
+"#;
+
+/// Parse the findings of an LLM reply to a prompt with LLM_PROMPT_FINDINGS_FORMAT.
+pub fn parse_llm_findings(text: &str) -> Option<Vec<LlmFinding>> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.strip_suffix("```"))
+        .unwrap_or(text);
+    serde_json::from_str(text).ok()
+}
+
 /// Shared prompt that tells the LLM it will be given a git diff before receiving instructions.
 pub const LLM_SHARED_PROMPT_DIFF: &str = r#"
 Evaluate the following git diff according to the instructions that follow.
 "#;
 
-#[derive(Clone, Copy)]
+/// An LLM lint check. The builtin checks are below, and more can be loaded from yaml files.
+#[derive(serde::Deserialize, Clone, Debug)]
 pub struct LlmCheck {
-    prompt: &'static str,
-    pub magic_all_good: &'static str,
-    pub topic: &'static str,
+    /// The name used to enable the check in the config.
+    pub name: Cow<'static, str>,
+    /// The instructions, followed by "# Output Format". The placeholder "{magic_all_good}" is
+    /// replaced by magic_all_good.
+    prompt: Cow<'static, str>,
+    /// The reply of the LLM, if nothing was found.
+    pub magic_all_good: Cow<'static, str>,
+    /// The heading for the findings in the metadata comment.
+    pub topic: Cow<'static, str>,
+    /// Only check files matching any of these globs. If empty, all files are checked.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Skip files matching any of these globs.
+    #[serde(default)]
+    pub exclude: Vec<String>,
 }
 
 impl LlmCheck {
     pub fn prompt(&self) -> String {
-        self.prompt.replace("{magic_all_good}", self.magic_all_good)
+        self.prompt
+            .replace("{magic_all_good}", &self.magic_all_good)
+    }
+
+    /// The prompt with the output format replaced by LLM_PROMPT_FINDINGS_FORMAT.
+    pub fn prompt_findings(&self) -> String {
+        let instructions = self.prompt.split("# Output Format").next().unwrap();
+        format!("{instructions}{LLM_PROMPT_FINDINGS_FORMAT}")
     }
+
+    /// Whether the file at the given path should be checked.
+    pub fn matches_path(&self, path: &str) -> bool {
+        path_filter_matches(&self.include, &self.exclude, path)
+    }
+
+    /// Return the part of the git diff that should be checked.
+    pub fn filter_diff(&self, diff: &str) -> String {
+        filter_diff_files(diff, &self.include, &self.exclude)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.prompt.contains("# Output Format") {
+            return Err(format!("{}: prompt lacks '# Output Format'", self.name));
+        }
+        if !self.prompt.contains("{magic_all_good}") {
+            return Err(format!("{}: prompt lacks '{{magic_all_good}}'", self.name));
+        }
+        validate_globs(&self.include).map_err(|e| format!("{}: {e}", self.name))?;
+        validate_globs(&self.exclude).map_err(|e| format!("{}: {e}", self.name))?;
+        Ok(())
+    }
+}
+
+pub fn validate_globs(patterns: &[String]) -> Result<(), String> {
+    for pattern in patterns {
+        glob::Pattern::new(pattern).map_err(|e| format!("{pattern}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Whether the path matches any of the globs. A `*` does not match a `/`, but `**` does.
+pub fn glob_matches(patterns: &[String], path: &str) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    patterns
+        .iter()
+        .any(|p| glob::Pattern::new(p).is_ok_and(|p| p.matches_with(path, options)))
+}
+
+/// Whether the path matches any of the include globs, or there are none, and none of the
+/// exclude globs.
+pub fn path_filter_matches(include: &[String], exclude: &[String], path: &str) -> bool {
+    (include.is_empty() || glob_matches(include, path)) && !glob_matches(exclude, path)
+}
+
+/// Return the diffs of the files whose path passes path_filter_matches.
+pub fn filter_diff_files(diff: &str, include: &[String], exclude: &[String]) -> String {
+    split_diff_files(diff)
+        .into_iter()
+        .filter(|(path, _)| path_filter_matches(include, exclude, path))
+        .map(|(_, file_diff)| file_diff)
+        .collect()
+}
+
+/// Split a git diff into the diffs of each file, along with the path of the file.
+pub fn split_diff_files(diff: &str) -> Vec<(&str, &str)> {
+    let mut starts = diff
+        .match_indices("diff --git ")
+        .map(|(i, _)| i)
+        .filter(|&i| i == 0 || diff[..i].ends_with('\n'))
+        .collect::<Vec<_>>();
+    starts.push(diff.len());
+    starts
+        .windows(2)
+        .map(|w| {
+            let file_diff = &diff[w[0]..w[1]];
+            // Format: diff --git a/path b/path
+            let header = file_diff.lines().next().unwrap_or_default();
+            let path = header
+                .rsplit_once(" b/")
+                .map(|(_, p)| p)
+                .unwrap_or_default();
+            (path, file_diff)
+        })
+        .collect()
 }
 
 /// Prompt encouraging the LLM to highlight typos in git diff documentation.
@@ -198,101 +684,242 @@ If none are found, state: "{magic_all_good}".
 "#;
 
 pub static LLM_TYPOS: LlmCheck = LlmCheck {
-    prompt: LLM_PROMPT_TYPOS,
-    magic_all_good: "No typos were found",
-    topic: "Possible typos and grammar issues:",
+    name: Cow::Borrowed("typos"),
+    prompt: Cow::Borrowed(LLM_PROMPT_TYPOS),
+    magic_all_good: Cow::Borrowed("No typos were found"),
+    topic: Cow::Borrowed("Possible typos and grammar issues:"),
+    include: Vec::new(),
+    exclude: Vec::new(),
 };
 
 pub static LLM_NAMED_ARGS: LlmCheck = LlmCheck {
-    prompt: LLM_PROMPT_NAMED_ARGS,
-    magic_all_good: "No suggestions were found",
-    topic: "Possible places where named args for integral literals may be used (e.g. `func(x, /*named_arg=*/0)` in C++, and `func(x, named_arg=0)` in Python):",
+    name: Cow::Borrowed("named_args"),
+    prompt: Cow::Borrowed(LLM_PROMPT_NAMED_ARGS),
+    magic_all_good: Cow::Borrowed("No suggestions were found"),
+    topic: Cow::Borrowed("Possible places where named args for integral literals may be used (e.g. `func(x, /*named_arg=*/0)` in C++, and `func(x, named_arg=0)` in Python):"),
+    include: Vec::new(),
+    exclude: Vec::new(),
 };
 
 pub static LLM_CMP_MACROS: LlmCheck = LlmCheck {
-    prompt: LLM_PROMPT_CMP_MACROS,
-    magic_all_good: "No comparison macro suggestions were found.",
-    topic:
+    name: Cow::Borrowed("cmp_macros"),
+    prompt: Cow::Borrowed(LLM_PROMPT_CMP_MACROS),
+    magic_all_good: Cow::Borrowed("No comparison macro suggestions were found."),
+    topic: Cow::Borrowed(
         "Possible places where comparison-specific test macros should replace generic comparisons:",
+    ),
+    include: Vec::new(),
+    exclude: Vec::new(),
 };
 
-/// Return all available LLM lint checks
+/// Return all builtin LLM lint checks
 pub fn all_llm_checks() -> Vec<LlmCheck> {
-    vec![LLM_TYPOS, LLM_NAMED_ARGS, LLM_CMP_MACROS]
+    vec![
+        LLM_TYPOS.clone(),
+        LLM_NAMED_ARGS.clone(),
+        LLM_CMP_MACROS.clone(),
+    ]
 }
 
-/// Construct the OpenAI chat payload used by llm clients that request diff checks.
-pub fn make_llm_payload(diff: &str, check_prompt: String) -> serde_json::Value {
-    serde_json::json!({
-      "model": "gpt-5.4-mini",
-      "messages": [
-        {
-          "role": "developer",
-          "content": [
-            {
-              "type": "text",
-              "text": LLM_SHARED_PROMPT_DIFF
-            }
-          ]
-        },
-        {
-          "role": "user",
-          "content": [
-            {
-              "type": "text",
-              "text": diff
-            },
-            {
-              "type": "text",
-              "text": check_prompt
-            }
-          ]
+/// Construct the chat messages used by llm clients that request diff checks.
+pub fn make_llm_messages(diff: &str, check_prompt: String) -> Vec<llm::LlmMessage> {
+    vec![
+        llm::LlmMessage::system(LLM_SHARED_PROMPT_DIFF),
+        llm::LlmMessage::user(diff),
+        llm::LlmMessage::user(&check_prompt),
+    ]
+}
+
+/// Keys of the hidden machine-readable state that tools may store in the metadata comment.
+#[cfg(feature = "github")]
+pub enum MetaStateKey {
+    Conflicts,          // The list of conflicting pulls as of the last conflicts run
+    LlmLintHead,        // The head commit of the pull that was last checked by the LLM linter
+    LastPush,           // The date of the last push to the pull
+    LlmFindings,        // The LLM linter findings as of LlmLintHead, when linting incrementally
+    OverviewHead,       // The head commit of the pull that the LLM overview was generated for
+    ScriptedDiffHead,   // The head commit of the pull whose scripted-diffs were last verified
+    SuggestedReviewers, // The reviewers suggested as of the last push
+    ReviewersRequested, // Whether reviews were requested from the suggested reviewers
+}
+
+#[cfg(feature = "github")]
+impl MetaStateKey {
+    pub fn str(&self) -> &'static str {
+        match self {
+            Self::Conflicts => "conflicts",
+            Self::LlmLintHead => "llm_lint_head",
+            Self::LastPush => "last_push",
+            Self::LlmFindings => "llm_findings",
+            Self::OverviewHead => "overview_head",
+            Self::ScriptedDiffHead => "scripted_diff_head",
+            Self::SuggestedReviewers => "suggested_reviewers",
+            Self::ReviewersRequested => "reviewers_requested",
         }
-      ],
-      "response_format": {
-        "type": "text"
-      },
-      "reasoning_effort": "medium",
-      "service_tier": "default",
-      "store": true
-    })
+    }
 }
 
+/// Start of the hidden html comment that holds the json state in the metadata comment.
+#[cfg(feature = "github")]
+const META_STATE_START: &str = "<!--meta-state:";
+#[cfg(feature = "github")]
+const META_STATE_END: &str = "-->";
+
 #[cfg(feature = "github")]
 pub struct MetaComment {
     pull_num: u64,
     pub id: Option<octocrab::models::CommentId>,
     sections: Vec<String>,
+    state: serde_json::Map<String, serde_json::Value>,
+    /// The state values set since the last write.
+    state_changes: serde_json::Map<String, serde_json::Value>,
+    layout: MetaLayout,
+    /// The comment body as last read or written, to detect concurrent edits.
+    body: String,
+}
+
+/// Escape the magic section split string, so that user provided text can not corrupt the
+/// sections. The escaped text renders the same in markdown, except in code spans and fenced code
+/// blocks, where the `&lt;` is shown literally.
+#[cfg(feature = "github")]
+fn escape_section_text(text: &str) -> String {
+    text.replace("<!--", "&lt;!--")
+}
+
+/// Return the byte offsets of all section ids (`<!--` followed by 32 hex digits and `-->`).
+#[cfg(feature = "github")]
+fn find_section_ids(body: &str) -> Vec<usize> {
+    const ID_LEN: usize = "<!--".len() + 32 + "-->".len();
+    body.match_indices("<!--")
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            body.get(i..i + ID_LEN).is_some_and(|id| {
+                id.ends_with("-->") && id[4..36].bytes().all(|b| b.is_ascii_hexdigit())
+            })
+        })
+        .collect()
 }
 
 #[cfg(feature = "github")]
 impl MetaComment {
+    /// Parse the metadata comment body, which must start with the root section id.
+    fn from_body(
+        body: &str,
+        pull_num: u64,
+        id: Option<octocrab::models::CommentId>,
+        layout: &MetaLayout,
+    ) -> Self {
+        let orig_body = body;
+        let mut body = body.to_string();
+        let mut state = serde_json::Map::new();
+        if let Some(start) = body.find(META_STATE_START) {
+            let json_start = start + META_STATE_START.len();
+            if let Some(len) = body[json_start..].find(META_STATE_END) {
+                if let Ok(serde_json::Value::Object(s)) =
+                    serde_json::from_str(&body[json_start..json_start + len])
+                {
+                    state = s;
+                }
+                body.replace_range(start..json_start + len + META_STATE_END.len(), "");
+            }
+        }
+        let ids = find_section_ids(&body);
+        let sections = ids
+            .iter()
+            .skip(1) // The root section
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = ids.get(i + 2).copied().unwrap_or(body.len());
+                body[start..end].to_string()
+            })
+            .collect::<Vec<_>>();
+        MetaComment {
+            pull_num,
+            id,
+            sections,
+            state,
+            state_changes: serde_json::Map::new(),
+            layout: layout.clone(),
+            body: orig_body.to_string(),
+        }
+    }
+
     pub fn has_section(&self, section_id: &IdComment) -> bool {
         let id = section_id.str();
         self.sections.iter().any(|s| s.starts_with(id))
     }
 
+    /// Return the value stored in the hidden state of the metadata comment.
+    pub fn state(&self, key: MetaStateKey) -> Option<&serde_json::Value> {
+        self.state.get(key.str())
+    }
+
+    /// Set the value in the hidden state. It will be written along with the next call to
+    /// update_metadata_comment.
+    pub fn set_state(&mut self, key: MetaStateKey, value: serde_json::Value) {
+        if self.state.get(key.str()) != Some(&value) {
+            self.state.insert(key.str().to_string(), value.clone());
+            self.state_changes.insert(key.str().to_string(), value);
+        }
+    }
+
+    /// Re-parse the comment from the body that was changed concurrently, and apply the section
+    /// update and the state changes on top.
+    fn merge_concurrent(&mut self, body: &str, section: IdComment, text: &str) {
+        let changes = std::mem::take(&mut self.state_changes);
+        *self = Self::from_body(body, self.pull_num, self.id, &self.layout.clone());
+        self.update(section, text);
+        self.state.extend(changes.clone());
+        self.state_changes = changes;
+    }
+
+    /// Return the full text to write, after merging the current body, if it was changed
+    /// concurrently since it was last read or written.
+    fn text_to_write(&mut self, current: &str, section: IdComment, text: &str) -> String {
+        if current != self.body {
+            println!("... Comment changed concurrently, merge");
+            self.merge_concurrent(current, section, text);
+        }
+        self.join_metadata_comment()
+    }
+
+    /// Remember the body as written, after which the state changes are no longer pending.
+    fn written(&mut self, body: String) {
+        self.body = body;
+        self.state_changes.clear();
+    }
+
     fn join_metadata_comment(&mut self) -> String {
-        self.sections.sort();
-        let desc = "The following sections might be updated with supplementary metadata relevant to reviewers and maintainers.";
+        let layout = &self.layout;
+        self.sections
+            .sort_by(|a, b| (layout.sort_key(a), a).cmp(&(layout.sort_key(b), b)));
+        let state = if self.state.is_empty() {
+            "".to_string()
+        } else {
+            // Escape angle brackets, which can only appear in json strings, to avoid an early end
+            // of the html comment.
+            let json = serde_json::Value::Object(self.state.clone())
+                .to_string()
+                .replace('<', "\\u003c")
+                .replace('>', "\\u003e");
+            format!("{META_STATE_START}{json}{META_STATE_END}")
+        };
         format!(
-            "{root_id}\n\n{desc}\n\n{sec}",
+            "{root_id}\n\n{desc}\n\n{sec}{state}",
             root_id = IdComment::Metadata.str(),
+            desc = escape_section_text(&self.layout.description),
             sec = self.sections.join("")
         )
     }
 
     fn update(&mut self, id: IdComment, new_text: &str) -> bool {
-        // TODO. Maybe check if new_text contains "<!--" and reject it as Err("Must not contain
-        // magic section split string").
-        // Also, could reword the magic section split string to `<!---` (with 3 dashes, to avoid
-        // normal use collisions?)
+        let new_text = self.layout.render(id, &escape_section_text(new_text));
         let needle = id.str();
         let new_section = format!("{}{}", needle, new_text);
         for s in self.sections.iter_mut() {
             if s.starts_with(needle) {
                 // Section exists
-                let orig = s.split(needle).nth(1).unwrap();
+                let orig = &s[needle.len()..];
                 if orig == new_text {
                     // Section up to date
                     return false;
@@ -313,40 +940,30 @@ pub async fn get_metadata_sections(
     api: &octocrab::Octocrab,
     api_issues: &octocrab::issues::IssueHandler<'_>,
     pull_nr: u64,
+    layout: &MetaLayout,
 ) -> octocrab::Result<MetaComment> {
     let comments = api
         .all_pages(api_issues.list_comments(pull_nr).send().await?)
         .await?;
 
-    Ok(get_metadata_sections_from_comments(&comments, pull_nr))
+    Ok(get_metadata_sections_from_comments(
+        &comments, pull_nr, layout,
+    ))
 }
 
 #[cfg(feature = "github")]
 pub fn get_metadata_sections_from_comments(
     comments: &Vec<octocrab::models::issues::Comment>,
     pull_nr: u64,
+    layout: &MetaLayout,
 ) -> MetaComment {
     for c in comments {
         let b = c.body.as_ref().expect("remote api error");
         if b.starts_with(IdComment::Metadata.str()) {
-            let sections = b
-                .split("<!--")
-                .skip(2)
-                .map(|s| format!("<!--{}", s))
-                .collect::<Vec<_>>();
-
-            return MetaComment {
-                pull_num: pull_nr,
-                id: Some(c.id),
-                sections,
-            };
+            return MetaComment::from_body(b, pull_nr, Some(c.id), layout);
         }
     }
-    MetaComment {
-        pull_num: pull_nr,
-        id: None,
-        sections: Vec::new(),
-    }
+    MetaComment::from_body("", pull_nr, None, layout)
 }
 
 #[cfg(feature = "github")]
@@ -357,30 +974,43 @@ pub async fn update_metadata_comment(
     section: IdComment,
     dry_run: bool,
 ) -> octocrab::Result<()> {
-    if !comment.update(section, text) {
-        // Section up to date
+    if !comment.update(section, text) && comment.state_changes.is_empty() {
+        // Section and state up to date
         return Ok(());
     }
     if comment.id.is_none() {
         // Create new metadata comment
         let full_text = comment.join_metadata_comment();
         println!("... Create new metadata comment");
-        if !dry_run {
+        if dry_run {
+            comment.written(full_text);
+        } else {
             let c = api_issues
                 .create_comment(comment.pull_num, full_text)
                 .await?;
 
             comment.id = Some(c.id);
+            comment.written(c.body.unwrap_or_default());
         }
 
         return Ok(());
     }
-    let full_text = comment.join_metadata_comment();
     println!("... Update comment");
-    if !dry_run {
-        api_issues
-            .update_comment(comment.id.unwrap(), full_text)
-            .await?;
+    if dry_run {
+        let full_text = comment.join_metadata_comment();
+        comment.written(full_text);
+    } else {
+        // This is a read-modify-write of the whole comment. Re-read it right before the write, so
+        // that a concurrent edit by another tool is merged instead of lost. A small race remains.
+        let id = comment.id.unwrap();
+        let current = api_issues.get_comment(id).await?.body.unwrap_or_default();
+        let full_text = comment.text_to_write(&current, section, text);
+        let body = api_issues
+            .update_comment(id, full_text)
+            .await?
+            .body
+            .unwrap_or_default();
+        comment.written(body);
     }
     Ok(())
 }
@@ -403,3 +1033,346 @@ pub async fn get_pull_mergeable(
         return Ok(Some(pull));
     }
 }
+
+/// Return the branch of a needs-backport label, given the label format with a `{branch}`
+/// placeholder.
+pub fn needs_backport_branch<'a>(format: &str, label: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = format.split_once("{branch}")?;
+    label
+        .strip_prefix(prefix)?
+        .strip_suffix(suffix)
+        .filter(|b| !b.is_empty())
+}
+
+/// Return whether the text refers to the pull request, like `#123` or `owner/repo#123`.
+#[cfg(feature = "github")]
+fn mentions_pull(text: &str, repo_slug: &str, number: u64) -> bool {
+    let needle = format!("#{number}");
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(&needle).any(|(i, _)| {
+        let (before, after) = (&text[..i], &text[i + needle.len()..]);
+        !after.starts_with(is_word)
+            && (before.ends_with(repo_slug) || !before.ends_with(|c| is_word(c) || c == '/'))
+    })
+}
+
+/// Return the number and state of the pull requests against the branch that refer to the pull
+/// request in their title or description.
+#[cfg(feature = "github")]
+pub async fn find_backports(
+    api: &octocrab::Octocrab,
+    repo_slug: &str,
+    number: u64,
+    branch: &str,
+) -> octocrab::Result<Vec<(u64, String)>> {
+    let found = api
+        .get::<serde_json::Value, _, _>(
+            "/search/issues",
+            Some(&serde_json::json!({
+                "q": format!("repo:{repo_slug} is:pr base:{branch} {number}"),
+                "per_page": 100,
+            })),
+        )
+        .await?;
+    Ok(found["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|i| {
+            mentions_pull(i["title"].as_str().unwrap_or_default(), repo_slug, number)
+                || mentions_pull(i["body"].as_str().unwrap_or_default(), repo_slug, number)
+        })
+        .filter_map(|i| {
+            let state = if i["pull_request"]["merged_at"].is_string() {
+                "merged"
+            } else {
+                i["state"].as_str()?
+            };
+            Some((i["number"].as_u64()?, state.to_string()))
+        })
+        .collect())
+}
+
+/// A known intermittent CI failure, along with the issue tracking it. Stored as a yaml list, which
+/// is seeded by the seed_known_failures tool and may be edited manually.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct KnownFailure {
+    /// The failing test, like `wallet_basic.py` or `net_tests`. Also matches its arguments and
+    /// Boost.Test cases, like `net_tests/cnode_simple_test`.
+    pub test: String,
+    /// Optional. A regex matched against the error message. Any failure of the test matches, if
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The url of the issue.
+    pub issue: String,
+    #[serde(default)]
+    pub title: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = r#"diff --git a/src/a.cpp b/src/a.cpp
+index 1111111..2222222 100644
+--- a/src/a.cpp
++++ b/src/a.cpp
+@@ -10,3 +10,4 @@ void f()
+ int a;
+-int b;
++int c;
++++d;
+ int e;
+diff --git a/src/old.cpp b/src/old.cpp
+deleted file mode 100644
+--- a/src/old.cpp
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+"#;
+
+    #[test]
+    fn test_parse_diff_right_side() {
+        let files = parse_diff_right_side(DIFF);
+        assert_eq!(files.len(), 1);
+        let lines = &files["src/a.cpp"];
+        assert_eq!(
+            lines.keys().copied().collect::<Vec<_>>(),
+            vec![10, 11, 12, 13]
+        );
+        assert_eq!(
+            lines[&12],
+            DiffLine {
+                added: true,
+                text: "++d;".to_string()
+            }
+        );
+        assert!(!lines[&13].added);
+        assert_eq!(
+            prepare_numbered_diff_for_llm(DIFF),
+            "diff --git a/src/a.cpp b/src/a.cpp\nindex 1111111..2222222 100644\n+++ b/src/a.cpp\n@@ (hunk header) @@\n10  int a;\n11 +int c;\n12 +++d;\n13  int e;\ndiff --git a/src/old.cpp b/src/old.cpp\ndeleted file mode 100644\n+++ /dev/null\n@@ (hunk header) @@"
+        );
+    }
+
+    #[test]
+    fn test_llm_check_filter_diff() {
+        let diff = "diff --git a/src/qt/main.cpp b/src/qt/main.cpp\n+a\ndiff --git a/src/qt/locale/bitcoin_de.ts b/src/qt/locale/bitcoin_de.ts\n+b\ndiff --git a/doc/diff --git.md b/doc/diff --git.md\n+c\n";
+        let files = split_diff_files(diff);
+        assert_eq!(
+            files.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+            vec![
+                "src/qt/main.cpp",
+                "src/qt/locale/bitcoin_de.ts",
+                "doc/diff --git.md"
+            ]
+        );
+        let check = LlmCheck {
+            include: vec!["src/qt/**".to_string()],
+            exclude: vec!["src/qt/locale/*.ts".to_string()],
+            ..LLM_TYPOS.clone()
+        };
+        assert!(check.validate().is_ok());
+        assert_eq!(
+            check.filter_diff(diff),
+            "diff --git a/src/qt/main.cpp b/src/qt/main.cpp\n+a\n"
+        );
+        assert!(!glob_matches(&["src/*.cpp".to_string()], "src/qt/main.cpp"));
+        assert_eq!(LLM_TYPOS.filter_diff(diff), diff);
+    }
+
+    #[test]
+    fn test_chunk_diff() {
+        let diff = r#"diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,3 +1,4 @@
+ one
++two
+ three
+-four
+@@ -10,1 +11,1 @@
+-ten
++eleven
+diff --git a/b.txt b/b.txt
+--- a/b.txt
++++ b/b.txt
+@@ -1,1 +1,1 @@
+-b
++B
+"#;
+        assert_eq!(chunk_diff(diff, 10_000), vec![diff.to_string()]);
+        let chunks = chunk_diff(diff, 80);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.starts_with("diff --git ")));
+        assert!(chunks[0].ends_with(" one\n+two\n three\n-four\n"));
+        assert!(chunks[2].starts_with("diff --git a/b.txt b/b.txt\n"));
+        // The line numbers are kept, even when a hunk is split
+        let chunks = chunk_diff(diff, 60);
+        let lines = chunks
+            .iter()
+            .flat_map(|c| parse_diff_right_side(c).remove("a.txt"))
+            .flatten()
+            .map(|(num, line)| (num, line.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                (1, "one".to_string()),
+                (2, "two".to_string()),
+                (3, "three".to_string()),
+                (11, "eleven".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_llm_findings() {
+        let expected = vec![LlmFinding {
+            file: "src/a.cpp".to_string(),
+            line: 11,
+            message: "typo".to_string(),
+        }];
+        let json = r#"[{"file": "src/a.cpp", "line": 11, "message": "typo"}]"#;
+        assert_eq!(parse_llm_findings(json), Some(expected.clone()));
+        assert_eq!(
+            parse_llm_findings(&format!("```json\n{json}\n```")),
+            Some(expected)
+        );
+        assert_eq!(parse_llm_findings("[]"), Some(vec![]));
+        assert_eq!(parse_llm_findings("No typos were found"), None);
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn test_backports() {
+        let format = "Needs backport ({branch})";
+        assert_eq!(
+            needs_backport_branch(format, "Needs backport (30.x)"),
+            Some("30.x")
+        );
+        assert_eq!(needs_backport_branch(format, "Needs backport ()"), None);
+        assert_eq!(needs_backport_branch(format, "Backport"), None);
+        assert!(mentions_pull("Backport of #123", "bitcoin/bitcoin", 123));
+        assert!(mentions_pull(
+            "Backports:\n- bitcoin/bitcoin#123.",
+            "bitcoin/bitcoin",
+            123
+        ));
+        assert!(!mentions_pull("#1234 and gui#123", "bitcoin/bitcoin", 123));
+        assert!(!mentions_pull(
+            "bitcoin-core/gui#123",
+            "bitcoin/bitcoin",
+            123
+        ));
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn test_meta_comment_roundtrip() {
+        let mut cmt = MetaComment::from_body("", 1, None, &MetaLayout::default());
+        assert!(cmt.update(
+            IdComment::SecReviews,
+            "\n### Reviews\nSee <!-- hidden -->\n"
+        ));
+        assert!(cmt.update(IdComment::SecConflicts, "\n### Conflicts\n<!--x-->\n"));
+        cmt.set_state(MetaStateKey::LlmLintHead, "<!--abc-->".into());
+        let body = cmt.join_metadata_comment();
+        assert_eq!(body.matches("<!--").count(), 4);
+
+        let mut parsed = MetaComment::from_body(&body, 1, None, &MetaLayout::default());
+        assert_eq!(parsed.sections, cmt.sections);
+        assert_eq!(
+            parsed.state(MetaStateKey::LlmLintHead),
+            Some(&"<!--abc-->".into())
+        );
+        assert!(!parsed.update(
+            IdComment::SecReviews,
+            "\n### Reviews\nSee <!-- hidden -->\n"
+        ));
+        assert!(parsed.update(IdComment::SecConflicts, "\n### Conflicts\nNone\n"));
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn test_meta_comment_legacy() {
+        let body = format!(
+            "{root}\n\nDesc\n\n{rev}\n### Reviews\n{cov}\n### Coverage\n",
+            root = IdComment::Metadata.str(),
+            rev = IdComment::SecReviews.str(),
+            cov = IdComment::SecCodeCoverage.str(),
+        );
+        let cmt = MetaComment::from_body(&body, 1, None, &MetaLayout::default());
+        assert_eq!(cmt.sections.len(), 2);
+        assert!(cmt.has_section(&IdComment::SecReviews));
+        assert!(cmt.has_section(&IdComment::SecCodeCoverage));
+        assert!(cmt.state.is_empty());
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn test_meta_comment_layout() {
+        let layout = MetaLayout {
+            description: "Desc".to_string(),
+            order: vec!["llm_linter".to_string(), "reviews".to_string()],
+            collapsed: vec!["reviews".to_string()],
+        };
+        let mut cmt = MetaComment::from_body("", 1, None, &layout);
+        assert!(cmt.update(IdComment::SecConflicts, "\n### Conflicts\nNone\n"));
+        assert!(cmt.update(IdComment::SecReviews, "\n### Reviews\nNone\n"));
+        assert!(cmt.update(IdComment::SecLmCheck, "\n### LLM Linter\nNone\n"));
+        assert!(cmt.update(IdComment::SecCodeCoverage, "\n### Coverage\nNone\n"));
+        let body = cmt.join_metadata_comment();
+        assert_eq!(
+            body,
+            format!(
+                "{root}\n\nDesc\n\n{lm}\n### LLM Linter\nNone\n{rev}\n<details><summary>Reviews</summary>\n\nNone\n</details>\n{cov}\n### Coverage\nNone\n{con}\n### Conflicts\nNone\n",
+                root = IdComment::Metadata.str(),
+                lm = IdComment::SecLmCheck.str(),
+                rev = IdComment::SecReviews.str(),
+                cov = IdComment::SecCodeCoverage.str(),
+                con = IdComment::SecConflicts.str(),
+            )
+        );
+        let mut parsed = MetaComment::from_body(&body, 1, None, &layout);
+        assert!(!parsed.update(IdComment::SecReviews, "\n### Reviews\nNone\n"));
+
+        assert_eq!(
+            MetaLayout::for_repo("maflcko/DrahtBot").collapsed,
+            vec!["llm_linter"]
+        );
+        assert!(MetaLayout::for_repo("unknown/repo").order.is_empty());
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn test_meta_comment_merge_concurrent() {
+        let mut other = MetaComment::from_body("", 1, None, &MetaLayout::default());
+        other.update(IdComment::SecConflicts, "\n### Conflicts\nNone\n");
+        other.set_state(MetaStateKey::LlmLintHead, "a".into());
+        let concurrent = other.join_metadata_comment();
+
+        let mut cmt = MetaComment::from_body("", 1, None, &MetaLayout::default());
+        cmt.update(IdComment::SecReviews, "\n### Reviews\nNone\n");
+        cmt.set_state(MetaStateKey::ScriptedDiffHead, "b".into());
+        cmt.merge_concurrent(&concurrent, IdComment::SecReviews, "\n### Reviews\nNone\n");
+        assert!(cmt.has_section(&IdComment::SecConflicts));
+        assert!(cmt.has_section(&IdComment::SecReviews));
+        assert_eq!(cmt.state(MetaStateKey::LlmLintHead), Some(&"a".into()));
+        assert_eq!(cmt.state(MetaStateKey::ScriptedDiffHead), Some(&"b".into()));
+
+        // The pending state change survives the merge on the write path, until written
+        let mut cmt = MetaComment::from_body("", 1, None, &MetaLayout::default());
+        cmt.set_state(MetaStateKey::ReviewersRequested, "c".into());
+        let text = cmt.text_to_write(&concurrent, IdComment::SecReviews, "\n### Reviews\nNone\n");
+        let written = MetaComment::from_body(&text, 1, None, &MetaLayout::default());
+        assert_eq!(
+            written.state(MetaStateKey::ReviewersRequested),
+            Some(&"c".into())
+        );
+        assert_eq!(written.state(MetaStateKey::LlmLintHead), Some(&"a".into()));
+        assert!(!cmt.state_changes.is_empty());
+        cmt.written(text);
+        assert!(cmt.state_changes.is_empty());
+    }
+}
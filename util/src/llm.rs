@@ -0,0 +1,611 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The providers of OpenAI-compatible chat completion endpoints.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LlmProvider {
+    #[serde(rename = "openai")]
+    OpenAi,
+    #[serde(rename = "gemini")]
+    Gemini,
+    /// A local OpenAI-compatible server, such as llama.cpp or Ollama.
+    #[serde(rename = "local")]
+    Local,
+}
+
+impl std::str::FromStr for LlmProvider {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(Self::OpenAi),
+            "gemini" => Ok(Self::Gemini),
+            "local" => Ok(Self::Local),
+            _ => Err("Unknown provider, must be one of: openai, gemini, local"),
+        }
+    }
+}
+
+impl LlmProvider {
+    fn backend(&self) -> Box<dyn LlmBackend> {
+        match self {
+            Self::OpenAi => Box::new(OpenAi),
+            Self::Gemini => Box::new(Gemini),
+            Self::Local => Box::new(Local),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LlmConfig {
+    pub provider: LlmProvider,
+    pub model: String,
+    /// Overrides the default chat completions endpoint of the provider.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    #[serde(default)]
+    pub verbosity: Option<String>,
+    /// The maximum time for a single request.
+    #[serde(default = "LlmConfig::default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// How often to retry a failed request.
+    #[serde(default = "LlmConfig::default_retries")]
+    pub retries: u32,
+    /// The environment variable with the token, for example when the providers differ. If not
+    /// set, the token is passed by the caller.
+    #[serde(default)]
+    pub token_env: Option<String>,
+}
+
+impl LlmConfig {
+    pub fn new(provider: LlmProvider, model: &str) -> Self {
+        Self {
+            provider,
+            model: model.to_string(),
+            url: None,
+            reasoning_effort: None,
+            verbosity: None,
+            timeout_secs: Self::default_timeout_secs(),
+            retries: Self::default_retries(),
+            token_env: None,
+        }
+    }
+
+    fn default_timeout_secs() -> u64 {
+        600
+    }
+
+    fn default_retries() -> u32 {
+        2
+    }
+}
+
+/// The provider-specific parts of a chat completions request.
+pub trait LlmBackend: Send + Sync {
+    /// The default chat completions endpoint.
+    fn url(&self) -> &'static str;
+
+    /// The role of the instructions that precede the user input.
+    fn system_role(&self) -> &'static str {
+        "system"
+    }
+
+    /// Add provider-specific fields to the payload.
+    fn extend_payload(&self, _payload: &mut serde_json::Map<String, serde_json::Value>) {}
+}
+
+struct OpenAi;
+
+impl LlmBackend for OpenAi {
+    fn url(&self) -> &'static str {
+        "https://api.openai.com/v1/chat/completions"
+    }
+
+    fn system_role(&self) -> &'static str {
+        "developer"
+    }
+
+    fn extend_payload(&self, payload: &mut serde_json::Map<String, serde_json::Value>) {
+        payload.insert("service_tier".to_string(), "default".into());
+        payload.insert("store".to_string(), true.into());
+    }
+}
+
+struct Gemini;
+
+impl LlmBackend for Gemini {
+    fn url(&self) -> &'static str {
+        // https://ai.google.dev/gemini-api/docs/openai
+        "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions"
+    }
+}
+
+struct Local;
+
+impl LlmBackend for Local {
+    fn url(&self) -> &'static str {
+        // The llama.cpp server default. For Ollama, set the url to
+        // http://127.0.0.1:11434/v1/chat/completions
+        "http://127.0.0.1:8080/v1/chat/completions"
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmRole {
+    System,
+    User,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct LlmMessage {
+    pub role: LlmRole,
+    pub text: String,
+}
+
+impl LlmMessage {
+    pub fn system(text: &str) -> Self {
+        Self {
+            role: LlmRole::System,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn user(text: &str) -> Self {
+        Self {
+            role: LlmRole::User,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// A request along with its reply, for example to collect feedback on it.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct LlmExchange {
+    pub model: String,
+    pub messages: Vec<LlmMessage>,
+    pub reply: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmVerdict {
+    Good,
+    Bad,
+}
+
+/// An LLM exchange labelled by feedback on the GitHub comment that it resulted in. Stored one
+/// per line in a jsonl dataset.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct LlmExample {
+    /// For example "lint", "ci_reason", or "spam".
+    pub kind: String,
+    /// The GitHub comment with the output.
+    pub url: String,
+    pub created_at: String,
+    #[serde(flatten)]
+    pub exchange: LlmExchange,
+    pub verdict: LlmVerdict,
+}
+
+pub fn read_llm_examples(path: &std::path::Path) -> std::io::Result<Vec<LlmExample>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).map_err(std::io::Error::other))
+        .collect()
+}
+
+pub fn append_llm_example(path: &std::path::Path, example: &LlmExample) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(example)?)
+}
+
+/// Token usage, as reported by the provider.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LlmUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl LlmUsage {
+    fn add(&mut self, other: &LlmUsage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+pub struct LlmReply {
+    pub text: String,
+    pub usage: LlmUsage,
+}
+
+#[derive(Debug)]
+pub enum LlmError {
+    Request(String),
+    Response(serde_json::Value),
+}
+
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(err) => write!(f, "LLM request error: {err}"),
+            Self::Response(response) => write!(f, "LLM response error: {response}"),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LlmCacheConfig {
+    /// The folder to put the cache files into. It is created, if missing.
+    pub dir: PathBuf,
+    /// Entries older than this are ignored and evicted.
+    #[serde(default = "LlmCacheConfig::default_ttl_days")]
+    pub ttl_days: u64,
+    /// When the total size exceeds this, the oldest entries are evicted.
+    #[serde(default = "LlmCacheConfig::default_max_mb")]
+    pub max_mb: u64,
+}
+
+impl LlmCacheConfig {
+    fn default_ttl_days() -> u64 {
+        30
+    }
+
+    fn default_max_mb() -> u64 {
+        100
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LlmCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// A content-addressed on-disk cache of LLM replies, which can be shared by several clients.
+pub struct LlmCache {
+    config: LlmCacheConfig,
+    stats: Mutex<LlmCacheStats>,
+    /// The total size of the entries in bytes, to avoid scanning the folder on every put.
+    size: Mutex<u64>,
+}
+
+impl LlmCache {
+    pub fn new(config: LlmCacheConfig) -> Self {
+        std::fs::create_dir_all(&config.dir).expect("must be able to create llm cache dir");
+        let size = Self::entries(&config.dir)
+            .iter()
+            .map(|(_, len, _)| len)
+            .sum();
+        Self {
+            config,
+            stats: Mutex::new(LlmCacheStats::default()),
+            size: Mutex::new(size),
+        }
+    }
+
+    pub fn stats(&self) -> LlmCacheStats {
+        *self.stats.lock().expect("stats mutex poisoned")
+    }
+
+    /// The sha256 of the full request payload, which includes the model, the prompts, and the
+    /// input.
+    fn key(payload: &serde_json::Value) -> String {
+        use sha2::{Digest, Sha256};
+        let hash = Sha256::digest(payload.to_string());
+        hash.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.ttl_days * 24 * 60 * 60)
+    }
+
+    fn is_expired(&self, modified: SystemTime) -> bool {
+        modified.elapsed().unwrap_or_default() > self.ttl()
+    }
+
+    /// Return the modification time, size and path of all files in the folder.
+    fn entries(dir: &std::path::Path) -> Vec<(SystemTime, u64, PathBuf)> {
+        let Ok(dir) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        dir.filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                Some((meta.modified().ok()?, meta.len(), e.path()))
+            })
+            .filter(|(_, _, path)| path.is_file())
+            .collect()
+    }
+
+    /// Remove the entry and return whether it existed.
+    fn remove(&self, path: &std::path::Path, len: u64) -> bool {
+        if std::fs::remove_file(path).is_err() {
+            return false;
+        }
+        let mut size = self.size.lock().expect("size mutex poisoned");
+        *size = size.saturating_sub(len);
+        true
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        let path = self.config.dir.join(key);
+        let text = match std::fs::metadata(&path) {
+            Ok(meta) if meta.modified().is_ok_and(|m| self.is_expired(m)) => {
+                if self.remove(&path, meta.len()) {
+                    self.stats.lock().expect("stats mutex poisoned").evictions += 1;
+                }
+                None
+            }
+            Ok(_) => std::fs::read_to_string(&path).ok(),
+            Err(_) => None,
+        };
+        let stats = {
+            let mut stats = self.stats.lock().expect("stats mutex poisoned");
+            match text {
+                Some(_) => stats.hits += 1,
+                None => stats.misses += 1,
+            }
+            *stats
+        };
+        println!(
+            " ... LLM cache {result} for {key} (hits: {hits}, misses: {misses}, evictions: {evictions})",
+            result = if text.is_some() { "hit" } else { "miss" },
+            hits = stats.hits,
+            misses = stats.misses,
+            evictions = stats.evictions,
+        );
+        text
+    }
+
+    fn put(&self, key: &str, text: &str) {
+        let path = self.config.dir.join(key);
+        let previous = std::fs::metadata(&path).map_or(0, |m| m.len());
+        if let Err(err) = std::fs::write(&path, text) {
+            println!(" ... Failed to write LLM cache entry {key}: {err}");
+            return;
+        }
+        let size = {
+            let mut size = self.size.lock().expect("size mutex poisoned");
+            *size = size.saturating_sub(previous) + text.len() as u64;
+            *size
+        };
+        if size > self.config.max_mb * 1024 * 1024 {
+            self.evict();
+        }
+    }
+
+    /// Remove expired entries, and then the oldest ones until the size limit is met.
+    fn evict(&self) {
+        let mut entries = Self::entries(&self.config.dir);
+        // Newest first, so that the oldest can be popped
+        entries.sort_by_key(|e| std::cmp::Reverse(e.0));
+        // Resync with the folder, in case it was changed by someone else
+        *self.size.lock().expect("size mutex poisoned") =
+            entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        let max = self.config.max_mb * 1024 * 1024;
+        let mut evicted = 0;
+        while let Some((modified, len, path)) = entries.pop() {
+            if *self.size.lock().expect("size mutex poisoned") <= max && !self.is_expired(modified)
+            {
+                break;
+            }
+            if self.remove(&path, len) {
+                evicted += 1;
+            }
+        }
+        self.stats.lock().expect("stats mutex poisoned").evictions += evicted;
+    }
+}
+
+pub struct LlmClient {
+    config: LlmConfig,
+    token: String,
+    backend: Box<dyn LlmBackend>,
+    http: reqwest::Client,
+    usage: std::sync::Mutex<LlmUsage>,
+    cache: Option<Arc<LlmCache>>,
+}
+
+impl LlmClient {
+    /// Create a client. The token may be empty for local servers.
+    pub fn new(config: LlmConfig, token: &str) -> Self {
+        Self {
+            backend: config.provider.backend(),
+            http: reqwest::Client::new(),
+            config,
+            token: token.to_string(),
+            usage: std::sync::Mutex::new(LlmUsage::default()),
+            cache: None,
+        }
+    }
+
+    /// Look up replies in the given cache first, and store new replies in it.
+    pub fn with_cache(mut self, cache: Arc<LlmCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    /// The total token usage of this client so far.
+    pub fn usage(&self) -> LlmUsage {
+        *self.usage.lock().expect("usage mutex poisoned")
+    }
+
+    fn payload(&self, messages: &[LlmMessage]) -> serde_json::Value {
+        let mut payload = serde_json::Map::new();
+        payload.insert("model".to_string(), self.config.model.clone().into());
+        payload.insert(
+            "messages".to_string(),
+            messages
+                .iter()
+                .map(|m| {
+                    serde_json::json!({
+                        "role": match m.role {
+                            LlmRole::System => self.backend.system_role(),
+                            LlmRole::User => "user",
+                        },
+                        "content": m.text,
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into(),
+        );
+        if let Some(effort) = &self.config.reasoning_effort {
+            payload.insert("reasoning_effort".to_string(), effort.clone().into());
+        }
+        if let Some(verbosity) = &self.config.verbosity {
+            payload.insert("verbosity".to_string(), verbosity.clone().into());
+        }
+        self.backend.extend_payload(&mut payload);
+        serde_json::Value::Object(payload)
+    }
+
+    async fn request(&self, payload: &serde_json::Value) -> Result<serde_json::Value, LlmError> {
+        let url = self.config.url.as_deref().unwrap_or(self.backend.url());
+        let mut request = self
+            .http
+            .post(url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .json(payload);
+        if !self.token.is_empty() {
+            request = request.bearer_auth(&self.token);
+        }
+        request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| LlmError::Request(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| LlmError::Request(e.to_string()))
+    }
+
+    /// Like chat, but return the messages along with the reply.
+    pub async fn exchange(&self, messages: Vec<LlmMessage>) -> Result<LlmExchange, LlmError> {
+        let reply = self.chat(&messages).await?.text;
+        Ok(LlmExchange {
+            model: self.config.model.clone(),
+            messages,
+            reply,
+        })
+    }
+
+    /// Send the messages and return the reply. Failed requests are retried as configured. Cached
+    /// replies are returned without usage.
+    pub async fn chat(&self, messages: &[LlmMessage]) -> Result<LlmReply, LlmError> {
+        let payload = self.payload(messages);
+        let cache_key = self.cache.as_ref().map(|_| LlmCache::key(&payload));
+        if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+            if let Some(text) = cache.get(key) {
+                return Ok(LlmReply {
+                    text,
+                    usage: LlmUsage::default(),
+                });
+            }
+        }
+        let mut attempt = 0;
+        let response = loop {
+            match self.request(&payload).await {
+                Ok(response) => break response,
+                Err(err) if attempt < self.config.retries => {
+                    attempt += 1;
+                    println!(" ... {err}, retry {attempt}/{}", self.config.retries);
+                    tokio::time::sleep(Duration::from_secs(5 * attempt as u64)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        let text = match response["choices"][0]["message"]["content"].as_str() {
+            Some(text) if !text.is_empty() => text.to_string(),
+            _ => return Err(LlmError::Response(response)),
+        };
+        let usage = LlmUsage {
+            requests: 1,
+            prompt_tokens: response["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            completion_tokens: response["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        };
+        let total = {
+            let mut total = self.usage.lock().expect("usage mutex poisoned");
+            total.add(&usage);
+            *total
+        };
+        println!(
+            " ... LLM usage ({model}): {prompt} prompt and {completion} completion tokens (total: {requests} requests, {total_prompt} prompt and {total_completion} completion tokens)",
+            model = self.config.model,
+            prompt = usage.prompt_tokens,
+            completion = usage.completion_tokens,
+            requests = total.requests,
+            total_prompt = total.prompt_tokens,
+            total_completion = total.completion_tokens,
+        );
+        if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+            cache.put(key, &text);
+        }
+        Ok(LlmReply { text, usage })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_age(cache: &LlmCache, key: &str, days: u64) {
+        std::fs::File::options()
+            .write(true)
+            .open(cache.config.dir.join(key))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_llm_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = LlmCache::new(LlmCacheConfig {
+            dir: dir.path().to_path_buf(),
+            ttl_days: 30,
+            max_mb: 1,
+        });
+        assert_eq!(cache.get("a"), None);
+        cache.put("a", "reply");
+        assert_eq!(cache.get("a").as_deref(), Some("reply"));
+
+        // Expired entries are deleted on get
+        set_age(&cache, "a", 31);
+        assert_eq!(cache.get("a"), None);
+        assert!(!dir.path().join("a").exists());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 2, 1));
+
+        // The oldest entries are evicted when the size limit is exceeded
+        let big = "x".repeat(600 * 1024);
+        cache.put("b", &big);
+        set_age(&cache, "b", 1);
+        cache.put("c", &big);
+        assert!(!dir.path().join("b").exists());
+        assert_eq!(cache.get("c").as_deref(), Some(big.as_str()));
+        assert_eq!(*cache.size.lock().unwrap(), big.len() as u64);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 2, 2));
+
+        // The size is restored from the folder
+        let cache = LlmCache::new(cache.config.clone());
+        assert_eq!(*cache.size.lock().unwrap(), big.len() as u64);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::ci_signatures::log_lines;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// The maximum number of rows in the failure table.
+const MAX_FAILURES: usize = 10;
+
+lazy_static! {
+    static ref CTEST_FAILED: Regex = Regex::new(r"^\s*\d+ - (\S+) \((.+)\)$").unwrap();
+    static ref BOOST_ERROR: Regex =
+        Regex::new(r#"^(\S+)\((\d+)\): (?:fatal )?error: in "([^"]+)": (.*)$"#).unwrap();
+    static ref FUNCTIONAL_FAILED: Regex =
+        Regex::new(r"^(\S+\.py\S*(?: \S+)*?) +\| ✖ Failed").unwrap();
+    static ref PY_FRAME: Regex = Regex::new(r#"^\s*File "([^"]+)", line (\d+)"#).unwrap();
+    static ref PY_ERROR: Regex = Regex::new(r"^\s*(\w+(?:Error|Exception)): (.*)$").unwrap();
+    static ref FUZZ_FAILED: Regex =
+        Regex::new(r"⚠️ Failure generated from target with exit code \d+: .*fuzz_corpora/([\w-]+)")
+            .unwrap();
+    static ref FUZZ_ERROR: Regex =
+        Regex::new(r"^(?:(\S+:\d+):\d+: (runtime error: .*)|==\d+== ?ERROR: (.*)|SUMMARY: (.*))$")
+            .unwrap();
+    static ref DIAGNOSTIC: Regex =
+        Regex::new(r"^(\S+):(\d+):\d+: (?:fatal )?(error|warning): (.*?)(?: \[([\w.,=-]+)\])?$")
+            .unwrap();
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub kind: &'static str,
+    /// The failing test, fuzz target, or clang-tidy check, if known.
+    pub name: String,
+    /// The file and line, if known.
+    pub location: Option<String>,
+    pub message: String,
+}
+
+/// Return the path relative to the source dir in the CI container.
+fn short_path(path: &str) -> &str {
+    let path = path.strip_prefix("/ci_container_base/").unwrap_or(path);
+    // Files copied to the build dir, like the functional tests
+    match path
+        .strip_prefix("ci/scratch/")
+        .and_then(|p| p.split_once('/'))
+    {
+        Some((_build_dir, p)) => p,
+        None => path,
+    }
+}
+
+/// Return the failing unit tests, along with the first Boost.Test error of each.
+fn parse_ctest(lines: &[&str]) -> Vec<Failure> {
+    lines
+        .iter()
+        .skip_while(|l| !l.starts_with("The following tests FAILED:"))
+        .skip(1)
+        .map_while(|l| CTEST_FAILED.captures(l))
+        .map(|cap| {
+            let name = cap[1].to_string();
+            let error = lines.iter().find_map(|l| {
+                BOOST_ERROR
+                    .captures(l)
+                    .filter(|e| e[3].split('/').next() == Some(name.as_str()))
+            });
+            match error {
+                Some(e) => Failure {
+                    kind: "unit test",
+                    name: e[3].to_string(),
+                    location: Some(format!("{}:{}", short_path(&e[1]), &e[2])),
+                    message: e[4].to_string(),
+                },
+                None => Failure {
+                    kind: "unit test",
+                    name,
+                    location: None,
+                    message: cap[2].to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Return the failing functional tests. The first Python error is attributed to the first
+/// failing test, because the logs of the tests are not separated.
+fn parse_functional(lines: &[&str]) -> Vec<Failure> {
+    let error = lines.iter().enumerate().find_map(|(i, l)| {
+        let e = PY_ERROR.captures(l)?;
+        let frame = lines[..i].iter().rev().find_map(|l| PY_FRAME.captures(l));
+        Some((
+            frame.map(|f| format!("{}:{}", short_path(&f[1]), &f[2])),
+            format!("{}: {}", &e[1], &e[2]),
+        ))
+    });
+    lines
+        .iter()
+        .filter_map(|l| FUNCTIONAL_FAILED.captures(l))
+        .enumerate()
+        .map(|(i, cap)| {
+            let (location, message) = match (i, &error) {
+                (0, Some((location, message))) => (location.clone(), message.clone()),
+                _ => (None, "Failed".to_string()),
+            };
+            Failure {
+                kind: "functional test",
+                name: cap[1].to_string(),
+                location,
+                message,
+            }
+        })
+        .collect()
+}
+
+/// Return the crashed fuzz targets, along with the first sanitizer or libFuzzer error.
+fn parse_fuzz(lines: &[&str]) -> Vec<Failure> {
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, l)| Some((i, FUZZ_FAILED.captures(l)?)))
+        .map(|(i, cap)| {
+            // The error is printed before the failure line
+            let start = lines[..i]
+                .iter()
+                .rposition(|l| FUZZ_FAILED.is_match(l))
+                .map_or(0, |p| p + 1);
+            let error = lines[start..i].iter().find_map(|l| FUZZ_ERROR.captures(l));
+            Failure {
+                kind: "fuzz target",
+                name: cap[1].to_string(),
+                location: error
+                    .as_ref()
+                    .and_then(|e| e.get(1))
+                    .map(|l| short_path(l.as_str()).to_string()),
+                message: error
+                    .as_ref()
+                    .and_then(|e| e.get(2).or(e.get(3)).or(e.get(4)))
+                    .map_or("Crash", |m| m.as_str())
+                    .to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Return the clang-tidy diagnostics, or the first compiler error.
+fn parse_diagnostics(lines: &[&str]) -> Vec<Failure> {
+    let tidy = lines
+        .iter()
+        .any(|l| l.contains("Failure generated from clang-tidy"));
+    let diagnostics = lines
+        .iter()
+        .filter_map(|l| DIAGNOSTIC.captures(l))
+        .filter(|d| &d[3] == "error" || tidy);
+    if tidy {
+        diagnostics
+            .map(|d| Failure {
+                kind: "clang-tidy",
+                name: d
+                    .get(5)
+                    .map_or("", |c| c.as_str())
+                    .split(',')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                location: Some(format!("{}:{}", short_path(&d[1]), &d[2])),
+                message: d[4].to_string(),
+            })
+            .collect()
+    } else {
+        diagnostics
+            .take(1)
+            .map(|d| Failure {
+                kind: "compiler error",
+                name: "".to_string(),
+                location: Some(format!("{}:{}", short_path(&d[1]), &d[2])),
+                message: d[4].to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Return the failing tests and errors found in the log tail.
+pub fn parse_failures(log: &str) -> Vec<Failure> {
+    let lines = log_lines(log);
+    let mut failures = parse_diagnostics(&lines);
+    failures.extend(parse_ctest(&lines));
+    failures.extend(parse_functional(&lines));
+    failures.extend(parse_fuzz(&lines));
+    failures
+}
+
+/// Return the failures as a compact markdown table.
+pub fn render_failures(failures: &[Failure]) -> String {
+    if failures.is_empty() {
+        return "".to_string();
+    }
+    let cell = |s: &str| s.replace('|', "\\|").replace('`', "'");
+    let mut text =
+        "| Failure | Name | Location | Message |\n| ------- | ---- | -------- | ------- |\n"
+            .to_string();
+    for f in failures.iter().take(MAX_FAILURES) {
+        text += &format!(
+            "| {kind} | {name} | {location} | {message} |\n",
+            kind = f.kind,
+            name = if f.name.is_empty() {
+                "".to_string()
+            } else {
+                format!("`{}`", cell(&f.name))
+            },
+            location = f
+                .location
+                .as_deref()
+                .map_or("".to_string(), |l| format!("`{}`", cell(l))),
+            message = cell(&f.message.chars().take(200).collect::<String>()),
+        );
+    }
+    if failures.len() > MAX_FAILURES {
+        text += &format!("\n(and {} more)\n", failures.len() - MAX_FAILURES);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_failures() {
+        assert_eq!(
+            parse_failures(include_str!("../testdata/ci_logs/build.log")),
+            vec![Failure {
+                kind: "compiler error",
+                name: "".to_string(),
+                location: Some("src/validation.cpp:3120".to_string()),
+                message: "no member named 'ActivateBestChainStep2' in 'Chainstate'".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse_failures(include_str!("../testdata/ci_logs/ctest.log")),
+            vec![Failure {
+                kind: "unit test",
+                name: "validation_chainstatemanager_tests/chainstatemanager_snapshot_init"
+                    .to_string(),
+                location: Some("src/test/validation_chainstatemanager_tests.cpp:412".to_string()),
+                message: "check chainman.ActiveHeight() == 110 has failed [109 != 110]".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse_failures(include_str!("../testdata/ci_logs/functional.log")),
+            vec![Failure {
+                kind: "functional test",
+                name: "mempool_limit.py".to_string(),
+                location: Some("test/functional/mempool_limit.py:412".to_string()),
+                message: "AssertionError: not(6 == 5)".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse_failures(include_str!("../testdata/ci_logs/tidy.log")),
+            vec![Failure {
+                kind: "clang-tidy",
+                name: "modernize-use-emplace".to_string(),
+                location: Some("src/net_processing.cpp:2871".to_string()),
+                message: "use emplace_back instead of push_back".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse_failures(include_str!("../testdata/ci_logs/fuzz.log")),
+            vec![Failure {
+                kind: "fuzz target",
+                name: "script_flags".to_string(),
+                location: Some("src/script/interpreter.cpp:1923".to_string()),
+                message:
+                    "runtime error: load of value 190, which is not a valid value for type 'bool'"
+                        .to_string(),
+            }]
+        );
+        let sanitizer = parse_failures(include_str!("../testdata/ci_logs/sanitizer.log"));
+        assert_eq!(sanitizer[0].name, "net_tests");
+        assert!(render_failures(&sanitizer).contains("| unit test | `net_tests` |  | Failed |"));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::ci_failures::Failure;
+use util::KnownFailure;
+
+pub struct KnownFailures {
+    entries: Vec<(KnownFailure, Option<regex::Regex>)>,
+}
+
+/// Return whether the failing test name is the test, or one of its arguments or test cases.
+fn is_test(test: &str, name: &str) -> bool {
+    name.strip_prefix(test)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', ' ']))
+}
+
+impl KnownFailures {
+    /// Parse the known failures from the file. A missing file means that none are known yet.
+    pub fn load(file: &std::path::Path) -> anyhow::Result<Self> {
+        let entries: Vec<KnownFailure> = match std::fs::File::open(file) {
+            Ok(f) => serde_yaml::from_reader(f)
+                .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow::anyhow!("{}: {e}", file.display())),
+        };
+        let entries = entries
+            .into_iter()
+            .map(|k| {
+                if k.test.is_empty() {
+                    return Err(anyhow::anyhow!("{}: empty test", k.issue));
+                }
+                let error = k
+                    .error
+                    .as_deref()
+                    .map(regex::Regex::new)
+                    .transpose()
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", k.issue))?;
+                Ok((k, error))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Return the first known failure matching the failure.
+    pub fn find(&self, failure: &Failure) -> Option<&KnownFailure> {
+        self.entries
+            .iter()
+            .find(|(k, error)| {
+                is_test(&k.test, &failure.name)
+                    && error.as_ref().is_none_or(|e| e.is_match(&failure.message))
+            })
+            .map(|(k, _)| k)
+    }
+}
+
+/// Return the comment text linking the issues of the known failures.
+pub fn render_known(known: &[&KnownFailure]) -> String {
+    if known.is_empty() {
+        return "".to_string();
+    }
+    let mut text = "Possibly known intermittent failures:\n".to_string();
+    for k in known {
+        text += &format!("* `{}`: {}\n", k.test, k.issue);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            r#"
+- test: mempool_limit.py
+  error: 'AssertionError: not\(\d+ == \d+\)'
+  issue: https://github.com/bitcoin/bitcoin/issues/1
+- test: net_tests
+  issue: https://github.com/bitcoin/bitcoin/issues/2
+"#,
+        )
+        .unwrap();
+        let known = KnownFailures::load(file.path()).unwrap();
+        let path = file.path().to_path_buf();
+        file.close().unwrap();
+        let failure = |name: &str, message: &str| Failure {
+            kind: "test",
+            name: name.to_string(),
+            location: None,
+            message: message.to_string(),
+        };
+        let issue = |f: &Failure| known.find(f).map(|k| k.issue.as_str());
+        assert_eq!(
+            issue(&failure(
+                "mempool_limit.py --v2transport",
+                "AssertionError: not(6 == 5)"
+            )),
+            Some("https://github.com/bitcoin/bitcoin/issues/1")
+        );
+        assert_eq!(issue(&failure("mempool_limit.py", "Failed")), None);
+        assert_eq!(
+            issue(&failure("net_tests/cnode_simple_test", "Failed")),
+            Some("https://github.com/bitcoin/bitcoin/issues/2")
+        );
+        assert_eq!(issue(&failure("net_tests_extra", "Failed")), None);
+        assert!(KnownFailures::load(&path).unwrap().entries.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+/// The builtin catalogue, used when no file is configured.
+const BUILTIN: &str = include_str!("../ci_signatures.yml");
+
+/// The maximum number of context lines per match.
+const MAX_CONTEXT_LINES: usize = 20;
+
+lazy_static::lazy_static! {
+    /// The timestamp prefix of the lines in GitHub Actions logs.
+    static ref TIMESTAMP: regex::Regex = regex::Regex::new(r"^\d{4}-\d\d-\d\dT[\d:.]+Z ").unwrap();
+}
+
+/// Return the lines of the log, without the timestamp prefixes.
+pub fn log_lines(log: &str) -> Vec<&str> {
+    log.lines()
+        .map(|l| TIMESTAMP.find(l).map_or(l, |t| &l[t.end()..]))
+        .collect()
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Category {
+    Build,
+    Unit,
+    Functional,
+    Lint,
+    Tidy,
+    Fuzz,
+    Sanitizer,
+    Infra,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Not caused by the pull request. No label is set.
+    Info,
+    /// Set the label, but do not comment.
+    Warning,
+    /// Set the label and comment.
+    Error,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Signature {
+    pub name: String,
+    /// Matched against each line of the log tail.
+    pub regex: String,
+    pub category: Category,
+    pub severity: Severity,
+    pub hint: String,
+    /// The number of lines before the matching line to extract.
+    #[serde(default)]
+    pub context_before: usize,
+    /// The number of lines after the matching line to extract.
+    #[serde(default)]
+    pub context_after: usize,
+    /// Optional. Skip the signature if this regex matches any line, for example to not treat
+    /// clang-tidy diagnostics as compiler errors.
+    pub unless: Option<String>,
+}
+
+pub struct SignatureMatch<'a> {
+    pub signature: &'a Signature,
+    /// The matching line along with the context lines.
+    pub context: String,
+}
+
+pub struct Catalogue {
+    signatures: Vec<(Signature, regex::Regex, Option<regex::Regex>)>,
+}
+
+impl Catalogue {
+    /// Parse the catalogue from the file, or the builtin one if none is given.
+    pub fn load(file: Option<&std::path::Path>) -> anyhow::Result<Self> {
+        let signatures: Vec<Signature> = match file {
+            Some(file) => serde_yaml::from_reader(
+                std::fs::File::open(file)
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?,
+            )
+            .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?,
+            None => serde_yaml::from_str(BUILTIN)?,
+        };
+        let signatures = signatures
+            .into_iter()
+            .map(|s| {
+                let re =
+                    |r: &str| regex::Regex::new(r).map_err(|e| anyhow::anyhow!("{}: {e}", s.name));
+                let unless = s.unless.as_deref().map(re).transpose()?;
+                let regex = re(&s.regex)?;
+                Ok((s, regex, unless))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { signatures })
+    }
+
+    /// Return the first match of each signature in the log, most severe first, and otherwise in
+    /// the catalogue order.
+    pub fn match_log(&self, log: &str) -> Vec<SignatureMatch<'_>> {
+        let lines = log_lines(log);
+        let mut matches = self
+            .signatures
+            .iter()
+            .filter(|(_, _, unless)| {
+                unless
+                    .as_ref()
+                    .is_none_or(|u| !lines.iter().any(|l| u.is_match(l)))
+            })
+            .filter_map(|(signature, re, _)| {
+                let pos = lines.iter().position(|l| re.is_match(l))?;
+                let start = pos.saturating_sub(signature.context_before.min(MAX_CONTEXT_LINES));
+                let end =
+                    (pos + 1 + signature.context_after.min(MAX_CONTEXT_LINES)).min(lines.len());
+                Some(SignatureMatch {
+                    signature,
+                    context: lines[start..end].join("\n"),
+                })
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|m| std::cmp::Reverse(m.signature.severity));
+        matches
+    }
+}
+
+/// Return the comment text for the matches.
+pub fn render_matches(matches: &[SignatureMatch]) -> String {
+    if matches.is_empty() {
+        return "".to_string();
+    }
+    let mut text = "| Category | Hint |\n| -------- | ---- |\n".to_string();
+    for m in matches {
+        text += &format!("| {} | {} |\n", m.signature.category, m.signature.hint);
+    }
+    text += &format!(
+        "\n<details><summary>Log excerpt</summary>\n\n```\n{}\n```\n\n</details>\n",
+        matches
+            .iter()
+            .map(|m| m.context.replace("```", "'''"))
+            .collect::<Vec<_>>()
+            .join("\n...\n")
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_catalogue() {
+        let catalogue = Catalogue::load(None).unwrap();
+        let categories = |log: &str| {
+            catalogue
+                .match_log(log)
+                .iter()
+                .map(|m| (m.signature.category, m.signature.severity))
+                .collect::<Vec<_>>()
+        };
+        use Category::*;
+        use Severity::*;
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/build.log")),
+            vec![(Build, Error), (Build, Error)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/ctest.log")),
+            vec![(Unit, Error)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/functional.log")),
+            vec![(Functional, Warning)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/lint.log")),
+            vec![(Lint, Error)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/tidy.log")),
+            vec![(Tidy, Error)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/fuzz.log")),
+            vec![(Fuzz, Error), (Sanitizer, Error)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/sanitizer.log")),
+            vec![(Unit, Error), (Sanitizer, Error)]
+        );
+        assert_eq!(
+            categories(include_str!("../testdata/ci_logs/infra.log")),
+            vec![(Infra, Info)]
+        );
+
+        let log = include_str!("../testdata/ci_logs/ctest.log");
+        let matches = catalogue.match_log(log);
+        assert!(matches[0]
+            .context
+            .contains("The following tests FAILED:\n\t 98 - validation_chainstatemanager_tests"));
+        assert!(render_matches(&matches).contains("| unit | A unit test failed."));
+    }
+}
@@ -2,13 +2,230 @@
 pub struct Repo {
     pub repo_slug: String,
     pub backport_label: Option<String>,
+    /// Labels per release branch, used instead of the backport_label.
+    #[serde(default)]
+    pub backport_branch_labels: std::collections::HashMap<String, String>,
+    /// Optional. The label format of merged pull requests that need a backport, with a `{branch}`
+    /// placeholder. The backport pull requests are noted in the metadata comment.
+    pub needs_backport_label: Option<String>,
     pub repo_labels: std::collections::HashMap<String, Vec<String>>,
+    /// Path globs per label, used when no title regex matches. The label covering the strict
+    /// majority of changed lines is picked.
+    #[serde(default)]
+    pub repo_label_paths: std::collections::HashMap<String, Vec<String>>,
+    /// Labels in the order to break ties between path labels. Unlisted labels come last.
+    #[serde(default)]
+    pub repo_label_priority: Vec<String>,
     pub spam_detection: bool,
     pub ci_status: bool,
+    /// Fall back to the `debug_pull_request_number_str` check run annotation to find the pull
+    /// request of a check suite.
+    #[serde(default)]
+    pub ci_status_annotations: bool,
+    /// Optional. A yaml file with the CI failure signatures, read on every use. Defaults to the
+    /// builtin ci_signatures.yml.
+    pub ci_signatures_file: Option<std::path::PathBuf>,
+    /// Optional. Link the issues of known intermittent CI failures.
+    pub ci_known_failures: Option<CiKnownFailures>,
     pub corecheck: bool,
+    /// Post the LLM linter findings as inline review comments instead of a metadata section.
+    #[serde(default)]
+    pub llm_inline_review: bool,
+    #[serde(default)]
+    pub llm_checks: LlmChecks,
+    #[serde(default)]
+    pub llm_lint: LlmLint,
+    /// Add an LLM-generated overview of the changes to the metadata comment.
+    #[serde(default)]
+    pub llm_overview: bool,
+    /// Optional. Check that the commit messages match their diff.
+    pub commit_checks: Option<CommitChecks>,
+    /// Optional. Warn about commits that need to be squashed or rebased.
+    pub commit_hygiene: Option<CommitHygiene>,
+    /// Optional. Suggest reviewers based on git blame and prior ACKs.
+    pub reviewer_suggestions: Option<ReviewerSuggestions>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CiKnownFailures {
+    /// A yaml file with the known failures, read on every use. See util::KnownFailure.
+    pub file: std::path::PathBuf,
+    /// Do not set the CI failed label, if all failed tasks only have known failures.
+    #[serde(default)]
+    pub skip_label: bool,
+    /// Re-run the failed tasks with only known failures, once per run.
+    #[serde(default)]
+    pub rerun: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ReviewerSuggestions {
+    /// A local clone with the full history, for git blame. Cloned if missing.
+    pub git_dir: std::path::PathBuf,
+    /// The number of suggested reviewers.
+    #[serde(default = "ReviewerSuggestions::default_max")]
+    pub max: usize,
+    /// Only count authored lines and ACKs that are more recent.
+    #[serde(default = "ReviewerSuggestions::default_since_days")]
+    pub since_days: u64,
+    /// Users to never suggest, in addition to the pull request author, existing reviewers, and
+    /// bots.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Request reviews from the suggested reviewers once, after the first Concept ACK.
+    #[serde(default)]
+    pub auto_request: bool,
+}
+
+impl ReviewerSuggestions {
+    fn default_max() -> usize {
+        5
+    }
+    fn default_since_days() -> u64 {
+        730
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CommitHygiene {
+    /// Set while there are fixup!/squash!, merge, or empty commits.
+    pub needs_squash_label: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CommitChecks {
+    /// A local git folder to fetch the pull request commits into. Created if missing.
+    pub git_dir: std::path::PathBuf,
+    /// Refactor commits touching files matching any of these globs are flagged.
+    #[serde(default)]
+    pub behavior_paths: Vec<String>,
+}
+
+impl CommitChecks {
+    pub fn validate(&self) -> Result<(), String> {
+        util::validate_globs(&self.behavior_paths)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct LlmLint {
+    /// Only lint files matching any of these globs. If empty, all files are linted.
+    pub include: Vec<String>,
+    /// Skip files matching any of these globs, for example vendored or generated files.
+    pub exclude: Vec<String>,
+    /// The approximate number of tokens of the diff to send in a single request.
+    pub chunk_tokens: usize,
+    /// The maximum number of concurrent requests.
+    pub parallel: usize,
+    /// Only lint the changes since the last linted head, and keep the previous findings whose
+    /// lines still exist.
+    pub incremental: bool,
+}
+
+impl Default for LlmLint {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            chunk_tokens: 30_000,
+            parallel: 4,
+            incremental: false,
+        }
+    }
+}
+
+impl LlmLint {
+    /// Return the part of the git diff that should be linted.
+    pub fn filter_diff(&self, diff: &str) -> String {
+        util::filter_diff_files(diff, &self.include, &self.exclude)
+    }
+
+    /// Roughly four characters per token.
+    pub fn chunk_chars(&self) -> usize {
+        self.chunk_tokens * 4
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        util::validate_globs(&self.include)?;
+        util::validate_globs(&self.exclude)
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct LlmChecks {
+    /// The names of the enabled builtin or custom checks. If empty, all builtin checks are enabled.
+    #[serde(default)]
+    pub enabled: Vec<String>,
+    /// Yaml files, each with a list of custom checks. Read on every use, so that they can be
+    /// edited without a restart.
+    #[serde(default)]
+    pub files: Vec<std::path::PathBuf>,
+}
+
+impl LlmChecks {
+    /// Return the enabled checks, in the order they were enabled.
+    pub fn load(&self) -> anyhow::Result<Vec<util::LlmCheck>> {
+        let mut available = util::all_llm_checks();
+        for file in &self.files {
+            let custom: Vec<util::LlmCheck> = serde_yaml::from_reader(
+                std::fs::File::open(file)
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?,
+            )
+            .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?;
+            available.extend(custom);
+        }
+        for check in &available {
+            check.validate().map_err(anyhow::Error::msg)?;
+        }
+        if self.enabled.is_empty() {
+            return Ok(util::all_llm_checks());
+        }
+        self.enabled
+            .iter()
+            .map(|name| {
+                available
+                    .iter()
+                    .rfind(|c| c.name == name.as_str())
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown llm check: {name}"))
+            })
+            .collect()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Llm {
+    /// Used for the LLM linter checks of pull request diffs.
+    pub lint: util::llm::LlmConfig,
+    /// Used to summarize the reason of a CI failure.
+    pub ci_reason: util::llm::LlmConfig,
+    /// Used to detect spam.
+    pub spam: util::llm::LlmConfig,
+    /// Optional. Shared by all of the above.
+    pub cache: Option<util::llm::LlmCacheConfig>,
+    /// Optional. Collect feedback on the LLM outputs.
+    pub feedback: Option<LlmFeedback>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LlmFeedback {
+    /// The folder for the pending outputs and the labelled dataset.jsonl for llm_eval.
+    pub dir: std::path::PathBuf,
+    /// The reactions are read when the pull request is closed, or after this. Outputs without
+    /// feedback are dropped then.
+    #[serde(default = "LlmFeedback::default_max_age_days")]
+    pub max_age_days: u64,
+}
+
+impl LlmFeedback {
+    fn default_max_age_days() -> u64 {
+        30
+    }
 }
 
 #[derive(serde::Deserialize)]
 pub struct Config {
     pub repositories: Vec<Repo>,
+    pub llm: Llm,
 }
@@ -1,10 +1,13 @@
+use super::llm_feedback::{self, LlmComment};
 use super::{Feature, FeatureMeta};
+use crate::ci_failures::{parse_failures, render_failures, Failure};
+use crate::ci_known_failures::{render_known, KnownFailures};
+use crate::ci_signatures::{render_matches, Catalogue, Severity, SignatureMatch};
 use crate::errors::DrahtBotError;
 use crate::errors::Result;
 use crate::Context;
 use crate::GitHubEvent;
 use async_trait::async_trait;
-use std::process::{Command, Stdio};
 
 pub struct CiStatusFeature {
     meta: FeatureMeta,
@@ -15,8 +18,8 @@ impl CiStatusFeature {
         Self {
             meta: FeatureMeta::new(
                 "CI Status",
-                "Set a label for a failing CI status. Must also be enabled in the config yaml.",
-                vec![GitHubEvent::CheckSuite],
+                "Set a label for a failing CI status. Must also be enabled in the config yaml. The pull request heads are indexed to map check suites of forks to their pull request.",
+                vec![GitHubEvent::CheckSuite, GitHubEvent::PullRequest],
             ),
         }
     }
@@ -34,7 +37,7 @@ impl Feature for CiStatusFeature {
         event: &GitHubEvent,
         payload: &serde_json::Value,
     ) -> Result<()> {
-        let ci_failed_label = "CI failed";
+        let ci_failed_label = util::LABEL_CI_FAILED;
         let action = payload["action"]
             .as_str()
             .ok_or(DrahtBotError::KeyNotFound)?;
@@ -51,16 +54,29 @@ impl Feature for CiStatusFeature {
             "Handling: {repo_user}/{repo_name} {event}::{action} ({feature_name})",
             feature_name = self.meta().name()
         );
-        if !ctx
+        let Some(config_repo) = ctx
             .config
             .repositories
             .iter()
             .find(|r| r.repo_slug == format!("{}/{}", repo_user, repo_name))
-            .is_some_and(|c| c.ci_status)
-        {
+            .filter(|c| c.ci_status)
+        else {
             return Ok(());
-        }
+        };
         match event {
+            GitHubEvent::PullRequest
+                if action == "opened" || action == "synchronize" || action == "reopened" =>
+            {
+                // https://docs.github.com/en/webhooks/webhook-events-and-payloads?actionType=synchronize#pull_request
+                let number = payload["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let head_sha = payload["pull_request"]["head"]["sha"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                ctx.head_index
+                    .insert(&config_repo.repo_slug, head_sha, number);
+            }
             GitHubEvent::CheckSuite if action == "completed" => {
                 // https://docs.github.com/en/webhooks/webhook-events-and-payloads?actionType=completed#check_suite
                 let conclusion = payload["check_suite"]["conclusion"]
@@ -81,35 +97,13 @@ impl Feature for CiStatusFeature {
                     .send()
                     .await?
                     .check_runs;
-                let pull_number = {
-                    let mut pull_number = None;
-                    // Hacky way to get the pull number. See also https://github.com/maflcko/DrahtBot/issues/59#issuecomment-3472438198
-                    for check_run in check_runs.iter().filter(|c| c.output.annotations_count > 0) {
-                        let annotations = checks_api
-                            .list_annotations(check_run.id)
-                            .per_page(99)
-                            .send()
-                            .await?;
-                        if let Some(pr_str) = annotations.iter().find(|a| {
-                            a.title.as_deref().unwrap_or_default()
-                                == "debug_pull_request_number_str"
-                        }) {
-                            pull_number = Some(
-                                pr_str
-                                    .message
-                                    .as_deref()
-                                    .ok_or(DrahtBotError::KeyNotFound)?
-                                    .parse::<u64>()?,
-                            );
-                            break;
-                        }
-                    }
-                    pull_number
-                };
-                if pull_number.is_none() {
+                let Some(pull_number) =
+                    resolve_pull_number(ctx, config_repo, payload, &checks_api, &check_runs)
+                        .await?
+                else {
+                    println!("... no pull number found");
                     return Ok(());
-                }
-                let pull_number = pull_number.unwrap();
+                };
                 println!("... pull number {pull_number} conclusion: {conclusion}");
                 let issues_api = ctx.octocrab.issues(repo_user, repo_name);
                 let issue = issues_api.get(pull_number).await?;
@@ -129,6 +123,82 @@ impl Feature for CiStatusFeature {
                             .await?;
                     }
                 } else if !found_label && !success {
+                    let catalogue = Catalogue::load(config_repo.ci_signatures_file.as_deref())?;
+                    let known_failures = config_repo
+                        .ci_known_failures
+                        .as_ref()
+                        .map(|k| KnownFailures::load(&k.file))
+                        .transpose()?;
+                    let mut failed_runs = Vec::new();
+                    for run in check_runs.iter().filter(|r| {
+                        matches!(r.conclusion.as_deref(), Some("failure" | "timed_out"))
+                    }) {
+                        // A missing log is treated like an unknown failure, so the label is set
+                        let text = if ctx.dry_run {
+                            String::new()
+                        } else {
+                            get_log_tail(ctx, repo_user, repo_name, run.id.into_inner())
+                                .await
+                                .unwrap_or_default()
+                        };
+                        failed_runs.push((run, text));
+                    }
+                    let matches = failed_runs
+                        .iter()
+                        .map(|(run, text)| {
+                            (run, text, catalogue.match_log(text), parse_failures(text))
+                        })
+                        .collect::<Vec<_>>();
+                    let is_infra = |m: &[SignatureMatch]| {
+                        !m.is_empty() && m.iter().all(|m| m.signature.severity == Severity::Info)
+                    };
+                    let is_known = |f: &[Failure]| {
+                        known_failures
+                            .as_ref()
+                            .is_some_and(|k| !f.is_empty() && f.iter().all(|f| k.find(f).is_some()))
+                    };
+                    // Only skip, if every failed task has a known infra issue
+                    let infra_only =
+                        !matches.is_empty() && matches.iter().all(|(_, _, m, _)| is_infra(m));
+                    if infra_only {
+                        println!(
+                            "... {} skip label '{}', only infra issues found",
+                            pull_number, ci_failed_label
+                        );
+                        return Ok(());
+                    }
+                    let known_only = !matches.is_empty()
+                        && matches
+                            .iter()
+                            .all(|(_, _, m, f)| is_infra(m) || is_known(f));
+                    if let Some(config_known) = config_repo
+                        .ci_known_failures
+                        .as_ref()
+                        .filter(|_| known_only)
+                    {
+                        if config_known.rerun {
+                            let mut rerun = false;
+                            for (run, _, _, _) in matches.iter().filter(|(_, _, _, f)| is_known(f))
+                            {
+                                rerun |= rerun_job(ctx, repo_user, repo_name, run.id.into_inner())
+                                    .await?;
+                            }
+                            if rerun {
+                                println!(
+                                    "... {} skip label '{}', re-run tasks with known failures",
+                                    pull_number, ci_failed_label
+                                );
+                                return Ok(());
+                            }
+                        }
+                        if config_known.skip_label {
+                            println!(
+                                "... {} skip label '{}', only known failures found",
+                                pull_number, ci_failed_label
+                            );
+                            return Ok(());
+                        }
+                    }
                     println!(
                         "... {} add label '{}' due to {}",
                         pull_number, ci_failed_label, conclusion
@@ -137,74 +207,66 @@ impl Feature for CiStatusFeature {
                         issues_api
                             .add_labels(pull_number, &[ci_failed_label.to_string()])
                             .await?;
-                        // Check if *compile* failed and add comment
-                        // (functional tests are ignored due to intermittent issues)
-                        for run in check_runs
+                    }
+                    let mut known = matches
+                        .iter()
+                        .flat_map(|(_, _, _, f)| f)
+                        .filter_map(|f| known_failures.as_ref()?.find(f))
+                        .collect::<Vec<_>>();
+                    known.sort_by(|a, b| a.issue.cmp(&b.issue));
+                    known.dedup_by(|a, b| a.issue == b.issue);
+                    // Comment on the first task with an error, or else with a known failure.
+                    // Other functional test failures are ignored, due to intermittent issues.
+                    let Some((run, text, signature_matches, failures)) = matches
+                        .iter()
+                        .find(|(_, _, m, _)| {
+                            m.iter().any(|m| m.signature.severity == Severity::Error)
+                        })
+                        .or_else(|| {
+                            matches.iter().find(|(_, _, _, f)| {
+                                f.iter().any(|f| {
+                                    known_failures.as_ref().is_some_and(|k| k.find(f).is_some())
+                                })
+                            })
+                        })
+                    else {
+                        return Ok(());
+                    };
+                    println!(
+                        "... {} found {:?}, known {:?}",
+                        pull_number,
+                        signature_matches
                             .iter()
-                            .filter(|r| r.conclusion.as_deref().unwrap_or_default() != "success")
-                        {
-                            let curl_out = Command::new("curl")
-                                .args([
-                                    "--fail",
-                                    "-L",
-                                    "-H",
-                                    "Accept: application/vnd.github+json",
-                                    "-H",
-                                    &format!("Authorization: Bearer {}", ctx.github_token),
-                                    "-H",
-                                    "X-GitHub-Api-Version: 2022-11-28",
-                                    &format!(
-                                        "https://api.github.com/repos/{}/{}/actions/jobs/{}/logs",
-                                        repo_user, repo_name, run.id
-                                    ),
-                                ])
-                                .stderr(Stdio::inherit())
-                                .output()
-                                .expect("Failed to execute curl");
-                            assert!(curl_out.status.success()); // Could ignore error code or use exit_ok()? in the future.
-                            let full_text = String::from_utf8_lossy(&curl_out.stdout);
-
-                            // excerpt
-                            let text = full_text
-                                .lines()
-                                .rev()
-                                .take(500) // lines
-                                .collect::<Vec<_>>()
-                                .into_iter()
-                                .rev()
-                                .collect::<Vec<_>>()
-                                .join("\n")
-                                .chars()
-                                .rev()
-                                .take(20_000) // unicode chars
-                                .collect::<Vec<_>>()
-                                .into_iter()
-                                .rev()
-                                .collect::<String>();
-
-                            if text.contains("make: *** [Makefile") // build
-                                || text.contains("Errors while running CTest")
-                                || text.contains("Error: Unexpected dependencies were detected. Check previous output.") // tidy (deps)
-                                || text.contains("ailure generated from") // lint, tidy, fuzz
-                        {
-                            let llm_reason = get_llm_reason(
-                                &text,
-                                &ctx.llm_token,
-                            )
-                            .await
-                            .unwrap_or("(empty)".to_string());
-                            let comment = format!(
-                                r#"{id}
+                            .map(|m| &m.signature.name)
+                            .collect::<Vec<_>>(),
+                        known.iter().map(|k| &k.issue).collect::<Vec<_>>()
+                    );
+                    if ctx.dry_run {
+                        return Ok(());
+                    }
+                    let llm_exchange = get_llm_reason(text, &ctx.llm.ci_reason).await.ok();
+                    let llm_reason = llm_exchange
+                        .as_ref()
+                        .map_or("(empty)", |e| e.reply.as_str());
+                    let comment = format!(
+                        r#"{id}
 {msg}
 <sub>Task `{check_name}`: {url}</sub>
 <sub>LLM reason (✨ experimental): {llm_reason}</sub>
+
+{known}
+{failures}
+{signatures}
 {hints}
 "#,
-                                id = util::IdComment::CiFailed.str(),
-                                msg = "🚧 At least one of the CI tasks failed.",
-                                check_name = run.name,
-                                url = run.html_url.as_deref().unwrap_or_default(),
-                                hints = r#"
+                        id = util::IdComment::CiFailed.str(),
+                        msg = "🚧 At least one of the CI tasks failed.",
+                        check_name = run.name,
+                        url = run.html_url.as_deref().unwrap_or_default(),
+                        known = render_known(&known),
+                        failures = render_failures(failures),
+                        signatures = render_matches(signature_matches),
+                        hints = r#"
 <details><summary>Hints</summary>
 
 Try to run the tests locally, according to the documentation. However, a CI failure may still
@@ -223,11 +285,19 @@ Leave a comment here, if you need help tracking down a confusing failure.
 
 </details>
 "#,
-                            );
-                            issues_api.create_comment(pull_number, comment).await?;
-                            break;
-                        }
-                        }
+                    );
+                    let comment = issues_api.create_comment(pull_number, comment).await?;
+                    if let Some(exchange) = llm_exchange {
+                        llm_feedback::record(
+                            ctx,
+                            &format!("{repo_user}/{repo_name}"),
+                            pull_number,
+                            LlmComment::Issue(comment.id.into_inner()),
+                            "ci_reason",
+                            comment.html_url.as_str(),
+                            std::slice::from_ref(&exchange),
+                        )
+                        .await;
                     }
                 }
             }
@@ -237,19 +307,202 @@ Leave a comment here, if you need help tracking down a confusing failure.
     }
 }
 
-async fn get_llm_reason(ci_log: &str, llm_token: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    println!(" ... Run LLM summary for CI failure.");
-    let payload = serde_json::json!({
-      "model": "gpt-5.4-nano",
-      "messages": [
+/// Return the tail of the log of the CI task, at most 500 lines and 20k unicode chars, or None if
+/// the log can not be fetched, for example because it expired.
+async fn get_log_tail(
+    ctx: &Context,
+    repo_user: &str,
+    repo_name: &str,
+    job_id: u64,
+) -> Option<String> {
+    let response = reqwest::Client::new()
+        .get(format!(
+            "https://api.github.com/repos/{repo_user}/{repo_name}/actions/jobs/{job_id}/logs"
+        ))
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header("User-Agent", "DrahtBot")
+        .bearer_auth(&ctx.github_token)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+    let full_text = match response {
+        Ok(r) => r.text().await,
+        Err(err) => Err(err),
+    };
+    let full_text = match full_text {
+        Ok(text) => text,
+        Err(err) => {
+            println!("... ERROR when fetching the log of job {job_id}: {err}");
+            return None;
+        }
+    };
+
+    let tail = full_text
+        .lines()
+        .rev()
+        .take(500) // lines
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>()
+        .join("\n")
+        .chars()
+        .rev()
+        .take(20_000) // unicode chars
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect::<String>();
+    Some(tail)
+}
+
+/// Re-run the job, if it is the first attempt. Return whether it was re-run.
+async fn rerun_job(ctx: &Context, repo_user: &str, repo_name: &str, job_id: u64) -> Result<bool> {
+    let job = ctx
+        .octocrab
+        .get::<serde_json::Value, _, ()>(
+            format!("/repos/{repo_user}/{repo_name}/actions/jobs/{job_id}"),
+            None,
+        )
+        .await?;
+    if job["run_attempt"].as_u64() != Some(1) {
+        return Ok(false);
+    }
+    println!("... re-run task {} (id: {job_id})", job["name"]);
+    if !ctx.dry_run {
+        // The response has no json body, so only check the status
+        octocrab::map_github_error(
+            ctx.octocrab
+                ._post(
+                    format!("/repos/{repo_user}/{repo_name}/actions/jobs/{job_id}/rerun"),
+                    None::<&()>,
+                )
+                .await?,
+        )
+        .await?;
+    }
+    Ok(true)
+}
+
+/// The heads of pull requests by repo slug, from pull_request events. Used to map check suites
+/// of forks, which have no pull_requests in the payload.
+#[derive(Default)]
+pub struct HeadIndex {
+    heads: std::sync::Mutex<std::collections::VecDeque<(String, String, u64)>>,
+}
+
+impl HeadIndex {
+    /// The number of heads to remember. The oldest ones are dropped first.
+    const MAX_LEN: usize = 10_000;
+
+    pub fn insert(&self, repo_slug: &str, head_sha: &str, number: u64) {
+        let mut heads = self.heads.lock().expect("head index poisoned");
+        heads.retain(|(s, _, n)| !(s == repo_slug && *n == number));
+        heads.push_back((repo_slug.to_string(), head_sha.to_string(), number));
+        if heads.len() > Self::MAX_LEN {
+            heads.pop_front();
+        }
+    }
+
+    pub fn get(&self, repo_slug: &str, head_sha: &str) -> Option<u64> {
+        let heads = self.heads.lock().expect("head index poisoned");
+        heads
+            .iter()
+            .find(|(s, h, _)| s == repo_slug && h == head_sha)
+            .map(|(_, _, n)| *n)
+    }
+}
+
+/// Return the number of the pull request in the repo whose head is the check suite head. Only
+/// set for pull requests from branches in the same repo.
+fn pull_from_suite(check_suite: &serde_json::Value, repo_url: &str) -> Option<u64> {
+    check_suite["pull_requests"].as_array()?.iter().find(|p| {
+        p["base"]["repo"]["url"] == repo_url && p["head"]["sha"] == check_suite["head_sha"]
+    })?["number"]
+        .as_u64()
+}
+
+/// Return the pull request number from the `debug_pull_request_number_str` annotation, which the
+/// CI config must emit. See also https://github.com/maflcko/DrahtBot/issues/59#issuecomment-3472438198
+async fn pull_from_annotations(
+    checks_api: &octocrab::checks::ChecksHandler<'_>,
+    check_runs: &[octocrab::models::checks::CheckRun],
+) -> Result<Option<u64>> {
+    for check_run in check_runs.iter().filter(|c| c.output.annotations_count > 0) {
+        let annotations = checks_api
+            .list_annotations(check_run.id)
+            .per_page(99)
+            .send()
+            .await?;
+        if let Some(pr_str) = annotations
+            .iter()
+            .find(|a| a.title.as_deref().unwrap_or_default() == "debug_pull_request_number_str")
         {
-          "role": "developer",
-          "content": [
-            {
-              "type": "text",
-              "text":
-r#"
+            return Ok(Some(
+                pr_str
+                    .message
+                    .as_deref()
+                    .ok_or(DrahtBotError::KeyNotFound)?
+                    .parse::<u64>()?,
+            ));
+        }
+    }
+    Ok(None)
+}
+
+/// Return the number of the pull request that the check suite ran on. Try the payload first,
+/// then the head index, then the commits API, and the annotations last, if enabled.
+async fn resolve_pull_number(
+    ctx: &Context,
+    config_repo: &crate::config::Repo,
+    payload: &serde_json::Value,
+    checks_api: &octocrab::checks::ChecksHandler<'_>,
+    check_runs: &[octocrab::models::checks::CheckRun],
+) -> Result<Option<u64>> {
+    let check_suite = &payload["check_suite"];
+    let repo_url = payload["repository"]["url"]
+        .as_str()
+        .ok_or(DrahtBotError::KeyNotFound)?;
+    let head_sha = check_suite["head_sha"]
+        .as_str()
+        .ok_or(DrahtBotError::KeyNotFound)?;
+    if let Some(number) = pull_from_suite(check_suite, repo_url) {
+        return Ok(Some(number));
+    }
+    if let Some(number) = ctx.head_index.get(&config_repo.repo_slug, head_sha) {
+        println!("... pull number from head index");
+        return Ok(Some(number));
+    }
+    let pulls = ctx
+        .octocrab
+        .get::<Vec<serde_json::Value>, _, ()>(
+            format!(
+                "/repos/{slug}/commits/{head_sha}/pulls",
+                slug = config_repo.repo_slug
+            ),
+            None,
+        )
+        .await?;
+    if let Some(number) = pulls
+        .iter()
+        .find(|p| p["state"] == "open" && p["head"]["sha"] == head_sha)
+        .and_then(|p| p["number"].as_u64())
+    {
+        println!("... pull number from commits api");
+        ctx.head_index
+            .insert(&config_repo.repo_slug, head_sha, number);
+        return Ok(Some(number));
+    }
+    if config_repo.ci_status_annotations {
+        println!("... pull number from annotations");
+        return pull_from_annotations(checks_api, check_runs).await;
+    }
+    Ok(None)
+}
+
+/// Prompt to summarize the reason of a CI failure from the tail of the CI log.
+const LLM_PROMPT_CI_REASON: &str = r#"
 Analyze the tail of a CI log to determine and communicate the underlying reason for the CI failure.
 
 Consider potential causes such as build errors, ctest errors, clang-tidy errors, lint test errors, or fuzz test errors, even if the log is truncated.
@@ -263,41 +516,53 @@ Consider potential causes such as build errors, ctest errors, clang-tidy errors,
 # Output Format
 
 A single short sentence summarizing the underlying reason for the CI failure.
-"#
+"#;
+
+async fn get_llm_reason(
+    ci_log: &str,
+    llm: &util::llm::LlmClient,
+) -> Result<util::llm::LlmExchange> {
+    println!(" ... Run LLM summary for CI failure.");
+    Ok(llm
+        .exchange(vec![
+            util::llm::LlmMessage::system(LLM_PROMPT_CI_REASON),
+            util::llm::LlmMessage::user(ci_log),
+        ])
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_resolvers() {
+        let repo_url = "https://api.github.com/repos/bitcoin/bitcoin";
+        let suite = serde_json::json!({
+            "head_sha": "aaaa",
+            "pull_requests": [
+                {
+                    "number": 1,
+                    "head": { "sha": "aaaa" },
+                    "base": { "repo": { "url": "https://api.github.com/repos/other/bitcoin" } }
+                },
+                {
+                    "number": 2,
+                    "head": { "sha": "aaaa" },
+                    "base": { "repo": { "url": repo_url } }
+                }
+            ]
+        });
+        assert_eq!(pull_from_suite(&suite, repo_url), Some(2));
+        let fork_suite = serde_json::json!({ "head_sha": "aaaa", "pull_requests": [] });
+        assert_eq!(pull_from_suite(&fork_suite, repo_url), None);
+
+        let index = HeadIndex::default();
+        index.insert("bitcoin/bitcoin", "aaaa", 1);
+        index.insert("bitcoin/bitcoin", "bbbb", 1);
+        index.insert("bitcoin-core/gui", "aaaa", 2);
+        assert_eq!(index.get("bitcoin/bitcoin", "aaaa"), None);
+        assert_eq!(index.get("bitcoin/bitcoin", "bbbb"), Some(1));
+        assert_eq!(index.get("bitcoin-core/gui", "aaaa"), Some(2));
     }
-          ]
-        },
-        {
-          "role": "user",
-          "content": [
-            {
-              "type": "text",
-              "text":ci_log
-              }
-          ]
-        }
-      ],
-      "response_format": {
-        "type": "text"
-      },
-      "verbosity": "low",
-      "reasoning_effort": "medium",
-      "service_tier": "default",
-      "store": true
-    });
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", llm_token))
-        .json(&payload)
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<serde_json::Value>()
-        .await?;
-    let text = response["choices"][0]["message"]["content"]
-        .as_str()
-        .ok_or(DrahtBotError::KeyNotFound)?
-        .to_string();
-    Ok(text)
 }
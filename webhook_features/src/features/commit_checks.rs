@@ -0,0 +1,293 @@
+use super::{Feature, FeatureMeta};
+use crate::config::CommitChecks;
+use crate::errors::{DrahtBotError, Result};
+use crate::Context;
+use crate::GitHubEvent;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub struct CommitChecksFeature {
+    meta: FeatureMeta,
+}
+
+impl CommitChecksFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Commit Checks",
+                "Check that the commit messages of a pull request match their diff (refactor, move-only, subject prefix), if enabled in the yaml config.",
+                vec![GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+lazy_static! {
+    /// A subject prefix, like `wallet: `, `net processing: ` or `wallet, refactor: `.
+    static ref PREFIX: Regex = Regex::new(r"^[a-z0-9_./-]+( [a-z0-9_./-]+)*(, ?[a-z0-9_./-]+( [a-z0-9_./-]+)*)*: \S").unwrap();
+    /// Anything that looks like an attempt at a subject prefix.
+    static ref PREFIX_LIKE: Regex = Regex::new(r"^\s*\[?[\w ,./-]{1,30}\]?\s*:").unwrap();
+    static ref ANSI: Regex = Regex::new("\x1b\\[[0-9;]*m").unwrap();
+}
+
+/// Return the problem with the prefix of the commit subject, if any.
+fn check_subject(subject: &str) -> Option<&'static str> {
+    if PREFIX.is_match(subject) {
+        None
+    } else if PREFIX_LIKE.is_match(subject) {
+        Some("Malformed subject prefix")
+    } else {
+        Some("Missing subject prefix")
+    }
+}
+
+fn is_refactor(subject: &str) -> bool {
+    subject
+        .split_once(':')
+        .is_some_and(|(prefix, _)| prefix.split(',').any(|p| p.trim() == "refactor"))
+}
+
+fn is_move_only(subject: &str) -> bool {
+    let subject = subject.to_lowercase();
+    ["move-only", "move only", "moveonly"]
+        .iter()
+        .any(|m| subject.contains(m))
+}
+
+/// Return the number of non-blank added or removed lines in the colored diff that were not
+/// detected as moved.
+fn count_unmoved_lines(colored_diff: &str) -> usize {
+    colored_diff
+        .lines()
+        .filter(|l| l.starts_with("\x1b[31m-") || l.starts_with("\x1b[32m+"))
+        .filter(|l| !ANSI.replace_all(l, "")[1..].trim().is_empty())
+        .count()
+}
+
+/// Fetch the commits of the pull request into the local git folder.
+fn fetch_pull(config: &CommitChecks, repo: &str, number: u64, depth: usize) -> Result<()> {
+    if !config.git_dir.join(".git").is_dir() {
+        println!(" ... Init git folder {}", config.git_dir.display());
+        util::git_output(util::git().args(["init", "--quiet"]).arg(&config.git_dir))?;
+    }
+    util::git_output(util::git().arg("-C").arg(&config.git_dir).args([
+        "fetch",
+        "--quiet",
+        &format!("--depth={depth}"),
+        &format!("https://github.com/{repo}"),
+        &format!("refs/pull/{number}/head"),
+    ]))?;
+    Ok(())
+}
+
+/// Return the diff of the commit, with moved lines colored differently.
+fn get_colored_diff(config: &CommitChecks, sha: &str) -> Result<String> {
+    Ok(util::git_output(
+        util::git()
+            .arg("-C")
+            .arg(&config.git_dir)
+            .args([
+                "-c",
+                "color.diff.old=red",
+                "-c",
+                "color.diff.new=green",
+                "-c",
+                "color.diff.oldMoved=magenta",
+                "-c",
+                "color.diff.newMoved=blue",
+                "-c",
+                "color.diff.oldMovedAlternative=magenta",
+                "-c",
+                "color.diff.newMovedAlternative=blue",
+                "show",
+                "--format=",
+                "--color=always",
+                "--color-moved=blocks",
+                "--color-moved-ws=allow-indentation-change",
+            ])
+            .arg(sha),
+    )?)
+}
+
+fn get_changed_paths(config: &CommitChecks, sha: &str) -> Result<Vec<String>> {
+    Ok(util::git_output(
+        util::git()
+            .arg("-C")
+            .arg(&config.git_dir)
+            .args(["show", "--format=", "--name-only"])
+            .arg(sha),
+    )?
+    .lines()
+    .map(|l| l.to_string())
+    .collect())
+}
+
+#[async_trait]
+impl Feature for CommitChecksFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        if action != "synchronize" && action != "opened" {
+            return Ok(());
+        }
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let repo_slug = format!("{repo_user}/{repo_name}");
+        let Some(config_repo) = ctx
+            .config
+            .repositories
+            .iter()
+            .find(|r| r.repo_slug == repo_slug)
+        else {
+            return Ok(());
+        };
+        let Some(config) = &config_repo.commit_checks else {
+            return Ok(());
+        };
+        let pr_number = payload["number"]
+            .as_u64()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        println!(
+            "Handling: {repo_slug} {event}::{action} ({feature_name})",
+            feature_name = self.meta().name()
+        );
+
+        let commits = ctx
+            .octocrab
+            .all_pages(
+                ctx.octocrab
+                    .get::<octocrab::Page<serde_json::Value>, _, _>(
+                        format!("/repos/{repo_slug}/pulls/{pr_number}/commits"),
+                        Some(&serde_json::json!({ "per_page": 100 })),
+                    )
+                    .await?,
+            )
+            .await?;
+        fetch_pull(config, &repo_slug, pr_number, commits.len() + 1)?;
+
+        let mut problems = Vec::new();
+        for c in &commits {
+            let sha = c["sha"].as_str().ok_or(DrahtBotError::KeyNotFound)?;
+            if c["parents"].as_array().is_some_and(|p| p.len() > 1) {
+                // Merge commits have no subject prefix and no diff of their own
+                continue;
+            }
+            let subject = c["commit"]["message"]
+                .as_str()
+                .unwrap_or_default()
+                .lines()
+                .next()
+                .unwrap_or_default();
+            let mut found = Vec::new();
+            if let Some(p) = check_subject(subject) {
+                found.push(p.to_string());
+            }
+            if is_refactor(subject) {
+                let touched = get_changed_paths(config, sha)?
+                    .into_iter()
+                    .filter(|p| util::glob_matches(&config.behavior_paths, p))
+                    .map(|p| format!("`{p}`"))
+                    .collect::<Vec<_>>();
+                if !touched.is_empty() {
+                    found.push(format!(
+                        "Refactor touches behavior-relevant files: {}",
+                        touched.join(", ")
+                    ));
+                }
+            }
+            if is_move_only(subject) {
+                let unmoved = count_unmoved_lines(&get_colored_diff(config, sha)?);
+                if unmoved > 0 {
+                    found.push(format!(
+                        "Move-only commit with {unmoved} added or removed lines that were not moved (`git show --color-moved=dimmed-zebra --color-moved-ws=allow-indentation-change {short}`)",
+                        short = &sha[..12]
+                    ));
+                }
+            }
+            for f in found {
+                problems.push(format!(
+                    "- {short} \"{subject}\": {f}",
+                    short = &sha[..12],
+                    subject = subject.replace('`', "'"),
+                ));
+            }
+        }
+
+        let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+        let mut cmt = util::get_metadata_sections(
+            &ctx.octocrab,
+            &issues_api,
+            pr_number,
+            &util::MetaLayout::for_repo(&config_repo.repo_slug),
+        )
+        .await?;
+        let text = if problems.is_empty() {
+            "".to_string()
+        } else {
+            format!(
+                "\n### Commit Checks\nThe following commits may not match their commit message. See [the guideline](https://github.com/{repo_slug}/blob/master/CONTRIBUTING.md#committing-patches) for details.\n\n{}\n",
+                problems.join("\n")
+            )
+        };
+        if !text.is_empty() || cmt.has_section(&util::IdComment::SecCommitChecks) {
+            util::update_metadata_comment(
+                &issues_api,
+                &mut cmt,
+                &text,
+                util::IdComment::SecCommitChecks,
+                ctx.dry_run,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_subject() {
+        assert_eq!(check_subject("wallet: Add foo"), None);
+        assert_eq!(check_subject("net processing: Add foo"), None);
+        assert_eq!(check_subject("wallet, refactor: Add foo"), None);
+        assert_eq!(
+            check_subject("Refactor: Add foo"),
+            Some("Malformed subject prefix")
+        );
+        assert_eq!(
+            check_subject("refactor:Add foo"),
+            Some("Malformed subject prefix")
+        );
+        assert_eq!(
+            check_subject("[wallet] Add foo"),
+            Some("Missing subject prefix")
+        );
+        assert_eq!(check_subject("Add foo"), Some("Missing subject prefix"));
+        assert!(is_refactor("wallet, refactor: Add foo"));
+        assert!(!is_refactor("wallet: refactor foo"));
+        assert!(is_move_only("refactor: Move foo to bar (move-only)"));
+    }
+
+    #[test]
+    fn test_count_unmoved_lines() {
+        let diff = "\x1b[1mdiff --git a/a.cpp b/a.cpp\x1b[m\n\x1b[1m--- a/a.cpp\x1b[m\n\x1b[1m+++ b/a.cpp\x1b[m\n\x1b[36m@@ -1,7 +1,3 @@\x1b[m\n\x1b[35m-int a() {\x1b[m\n\x1b[31m-\x1b[m\n int b() {\x1b[m\n\x1b[32m+\x1b[m\x1b[32mnamespace {\x1b[m\n\x1b[34m+\x1b[m\x1b[34m    int a() {\x1b[m\n\x1b[32m+\x1b[m\n";
+        assert_eq!(count_unmoved_lines(diff), 1);
+    }
+}
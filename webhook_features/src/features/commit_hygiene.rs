@@ -0,0 +1,333 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::{DrahtBotError, Result};
+use crate::Context;
+use crate::GitHubEvent;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub struct CommitHygieneFeature {
+    meta: FeatureMeta,
+}
+
+impl CommitHygieneFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Commit Hygiene",
+                "Warn about fixup!/squash!, merge, empty, WIP, and foreign commits in pull requests and toggle the needs-squash label, if enabled in the yaml config.",
+                vec![GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+lazy_static! {
+    static ref AUTOSQUASH: Regex = Regex::new(r"^(fixup|squash|amend)! ").unwrap();
+    static ref WIP: Regex = Regex::new(r"(?i)^\s*[\[(]?(wip|do not merge|dnm)\b").unwrap();
+}
+
+struct PullCommit {
+    oid: String,
+    subject: String,
+    merge: bool,
+    empty: bool,
+    /// The GitHub logins of the authors, if known.
+    authors: Vec<String>,
+}
+
+/// Return the warnings about the commits, and whether they need to be squashed.
+fn check_commits(pr_author: &str, title: &str, commits: &[PullCommit]) -> (Vec<String>, bool) {
+    let mut warnings = Vec::new();
+    let mut needs_squash = false;
+    if WIP.is_match(title) {
+        warnings.push("The title has a work-in-progress marker. Consider marking the pull request as draft instead.".to_string());
+    }
+    for c in commits {
+        let mut warn = |msg: &str| {
+            warnings.push(format!(
+                "{short} \"{subject}\": {msg}",
+                short = &c.oid[..12],
+                subject = c.subject.replace('`', "'"),
+            ))
+        };
+        if AUTOSQUASH.is_match(&c.subject) {
+            warn("Autosquash commit, which should be squashed into its target");
+            needs_squash = true;
+        }
+        if c.merge {
+            warn("Merge commit, the branch should be rebased instead");
+            needs_squash = true;
+        }
+        if c.empty {
+            warn("Empty commit, which should be dropped");
+            needs_squash = true;
+        }
+        if WIP.is_match(&c.subject) {
+            warn("Work-in-progress commit");
+        }
+        let others = c
+            .authors
+            .iter()
+            .filter(|a| !a.eq_ignore_ascii_case(pr_author))
+            .map(|a| format!("@\u{200b}{a}"))
+            .collect::<Vec<_>>();
+        if !others.is_empty() && c.authors.len() == others.len() {
+            warn(&format!(
+                "Authored by {}, not the pull request author",
+                others.join(", ")
+            ));
+        }
+    }
+    (warnings, needs_squash)
+}
+
+const PULL_COMMITS_QUERY: &str = r#"
+query($owner: String!, $name: String!, $number: Int!, $cursor: String) {
+  repository(owner: $owner, name: $name) {
+    pullRequest(number: $number) {
+      commits(first: 100, after: $cursor) {
+        pageInfo { hasNextPage endCursor }
+        nodes {
+          commit {
+            oid
+            messageHeadline
+            additions
+            deletions
+            changedFilesIfAvailable
+            parents { totalCount }
+            authors(first: 10) { nodes { user { login } } }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+async fn get_pull_commits(
+    ctx: &Context,
+    owner: &str,
+    name: &str,
+    number: u64,
+) -> Result<Vec<PullCommit>> {
+    let mut commits = Vec::new();
+    let mut cursor = serde_json::Value::Null;
+    loop {
+        let response: serde_json::Value = ctx
+            .octocrab
+            .graphql(&serde_json::json!({
+                "query": PULL_COMMITS_QUERY,
+                "variables": {
+                    "owner": owner,
+                    "name": name,
+                    "number": number,
+                    "cursor": cursor,
+                },
+            }))
+            .await?;
+        if let Some(errors) = response.get("errors") {
+            println!(" ... ERROR when fetching pull commits {errors}");
+            return Err(DrahtBotError::KeyNotFound.into());
+        }
+        let page = &response["data"]["repository"]["pullRequest"]["commits"];
+        for node in page["nodes"].as_array().ok_or(DrahtBotError::KeyNotFound)? {
+            let c = &node["commit"];
+            let lines = c["additions"].as_u64().unwrap_or_default()
+                + c["deletions"].as_u64().unwrap_or_default();
+            commits.push(PullCommit {
+                oid: c["oid"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?
+                    .to_string(),
+                subject: c["messageHeadline"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                merge: c["parents"]["totalCount"].as_u64().unwrap_or_default() > 1,
+                // Renames and mode changes have no changed lines, so prefer the file count
+                empty: c["changedFilesIfAvailable"]
+                    .as_u64()
+                    .map_or(lines == 0, |files| files == 0),
+                authors: c["authors"]["nodes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|a| a["user"]["login"].as_str())
+                    .map(|l| l.to_string())
+                    .collect(),
+            });
+        }
+        if page["pageInfo"]["hasNextPage"].as_bool() != Some(true) {
+            break;
+        }
+        cursor = page["pageInfo"]["endCursor"].clone();
+    }
+    Ok(commits)
+}
+
+#[async_trait]
+impl Feature for CommitHygieneFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        if action != "synchronize" && action != "opened" && action != "edited" {
+            return Ok(());
+        }
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let Some(config_repo) = ctx
+            .config
+            .repositories
+            .iter()
+            .find(|r| r.repo_slug == format!("{repo_user}/{repo_name}"))
+        else {
+            return Ok(());
+        };
+        let Some(config) = &config_repo.commit_hygiene else {
+            return Ok(());
+        };
+        println!(
+            "Handling: {repo_user}/{repo_name} {event}::{action} ({feature_name})",
+            feature_name = self.meta().name()
+        );
+        // https://docs.github.com/en/webhooks/webhook-events-and-payloads?actionType=synchronize#pull_request
+        let pull = &payload["pull_request"];
+        let pr_number = payload["number"]
+            .as_u64()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let pr_author = pull["user"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let title = pull["title"].as_str().ok_or(DrahtBotError::KeyNotFound)?;
+
+        let commits = get_pull_commits(ctx, repo_user, repo_name, pr_number).await?;
+        let (warnings, needs_squash) = check_commits(pr_author, title, &commits);
+
+        let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+        let found_label = pull["labels"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|l| l["name"] == config.needs_squash_label.as_str());
+        if found_label && !needs_squash {
+            println!(
+                "... {} remove label '{}'",
+                pr_number, config.needs_squash_label
+            );
+            if !ctx.dry_run {
+                issues_api
+                    .remove_label(pr_number, &config.needs_squash_label)
+                    .await?;
+            }
+        } else if !found_label && needs_squash {
+            println!(
+                "... {} add label '{}'",
+                pr_number, config.needs_squash_label
+            );
+            if !ctx.dry_run {
+                issues_api
+                    .add_labels(pr_number, std::slice::from_ref(&config.needs_squash_label))
+                    .await?;
+            }
+        }
+
+        let mut cmt = util::get_metadata_sections(
+            &ctx.octocrab,
+            &issues_api,
+            pr_number,
+            &util::MetaLayout::for_repo(&config_repo.repo_slug),
+        )
+        .await?;
+        let text = if warnings.is_empty() {
+            "".to_string()
+        } else {
+            format!(
+                "\n### Commit Hygiene\n{}\n\n{}\n",
+                if needs_squash {
+                    format!(
+                        "Please squash or rebase the commits below, see [the guideline](https://github.com/{slug}/blob/master/CONTRIBUTING.md#squashing-commits).",
+                        slug = config_repo.repo_slug
+                    )
+                } else {
+                    "Please check the commits below.".to_string()
+                },
+                warnings
+                    .iter()
+                    .map(|w| format!("- {w}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        };
+        if !text.is_empty() || cmt.has_section(&util::IdComment::SecCommitHygiene) {
+            util::update_metadata_comment(
+                &issues_api,
+                &mut cmt,
+                &text,
+                util::IdComment::SecCommitHygiene,
+                ctx.dry_run,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(subject: &str, merge: bool, empty: bool, authors: &[&str]) -> PullCommit {
+        PullCommit {
+            oid: "0123456789abcdef".to_string(),
+            subject: subject.to_string(),
+            merge,
+            empty,
+            authors: authors.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_commits() {
+        let (warnings, needs_squash) = check_commits(
+            "alice",
+            "wallet: Add foo",
+            &[
+                commit("wallet: Add foo", false, false, &["Alice"]),
+                commit("wallet: Add foo", false, false, &["alice", "bob"]),
+                commit("test: Add foo test", false, false, &["bob"]),
+                commit("WIP: more", false, false, &[]),
+            ],
+        );
+        assert!(!needs_squash);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("@\u{200b}bob"));
+        assert!(warnings[1].contains("Work-in-progress"));
+
+        let (warnings, needs_squash) = check_commits(
+            "alice",
+            "[WIP] wallet: Add foo",
+            &[
+                commit("fixup! wallet: Add foo", false, false, &[]),
+                commit("Merge branch 'master' into foo", true, false, &[]),
+                commit("wallet: Nothing", false, true, &[]),
+            ],
+        );
+        assert!(needs_squash);
+        assert_eq!(warnings.len(), 4);
+    }
+}
@@ -14,7 +14,7 @@ impl LabelsFeature {
         Self {
             meta: FeatureMeta::new(
                 "Labels",
-                "Guess and set labels on pull requests missing them, if they are set in the config yaml.",
+                "Guess and set labels on pull requests, and replace them on title or base changes, if they are set in the config yaml. Labels set or removed by humans are left alone. Note the backport pull requests of merged pull requests that need a backport.",
                 vec![GitHubEvent::PullRequest],
             ),
         }
@@ -75,23 +75,217 @@ impl Feature for LabelsFeature {
                         config_repo,
                         base_name,
                         &pull,
+                        &ctx.bot_username,
                         ctx.dry_run,
                     )
                     .await?;
                 }
             }
+            GitHubEvent::PullRequest
+                if (action == "closed" || action == "labeled")
+                    && payload["pull_request"]["merged"].as_bool() == Some(true) =>
+            {
+                // https://docs.github.com/en/webhooks/webhook-events-and-payloads?actionType=closed#pull_request
+                let Some(config_repo) = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| r.repo_slug == format!("{repo_user}/{repo_name}"))
+                else {
+                    return Ok(());
+                };
+                let Some(needs_backport_label) = &config_repo.needs_backport_label else {
+                    return Ok(());
+                };
+                let pr_number = payload["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let branches = payload["pull_request"]["labels"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|l| {
+                        util::needs_backport_branch(needs_backport_label, l["name"].as_str()?)
+                    })
+                    .collect::<Vec<_>>();
+                let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+                let mut cmt = util::get_metadata_sections(
+                    &ctx.octocrab,
+                    &issues_api,
+                    pr_number,
+                    &util::MetaLayout::for_repo(&config_repo.repo_slug),
+                )
+                .await?;
+                if branches.is_empty() && !cmt.has_section(&util::IdComment::SecBackports) {
+                    return Ok(());
+                }
+                let mut backports = Vec::new();
+                for branch in branches {
+                    let found = util::find_backports(
+                        &ctx.octocrab,
+                        &config_repo.repo_slug,
+                        pr_number,
+                        branch,
+                    )
+                    .await?;
+                    backports.push((branch.to_string(), found));
+                }
+                util::update_metadata_comment(
+                    &issues_api,
+                    &mut cmt,
+                    &render_backports(&backports),
+                    util::IdComment::SecBackports,
+                    ctx.dry_run,
+                )
+                .await?;
+            }
             _ => {}
         }
         Ok(())
     }
 }
 
+/// Return the release branch that the pull request is a backport to. This is the base branch, if
+/// it is not the default branch, or a release branch given as `[branch]` title prefix.
+fn backport_branch<'a>(
+    base: &'a str,
+    default_branch: &str,
+    title: &'a str,
+    release_branches: &[&str],
+) -> Option<&'a str> {
+    if base != default_branch {
+        return Some(base);
+    }
+    let branch = title.strip_prefix('[')?.split_once(']')?.0;
+    release_branches.contains(&branch).then_some(branch)
+}
+
+/// Return the section text for the backport pull requests per branch.
+fn render_backports(backports: &[(String, Vec<(u64, String)>)]) -> String {
+    if backports.is_empty() {
+        return "".to_string();
+    }
+    let mut text = "\n### Backports\n| Branch | Backport |\n| ------ | -------- |\n".to_string();
+    for (branch, found) in backports {
+        let found = if found.is_empty() {
+            "None found yet".to_string()
+        } else {
+            found
+                .iter()
+                .map(|(n, state)| format!("#{n} ({state})"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        text += &format!("| {branch} | {found} |\n");
+    }
+    text
+}
+
+/// Return the label whose path globs cover the strict majority of the changed lines, preferring
+/// labels earlier in the priority list on a tie.
+fn pick_label_by_paths(
+    files: &[(String, u64)],
+    label_paths: &std::collections::HashMap<String, Vec<String>>,
+    priority: &[String],
+) -> Option<String> {
+    let total = files.iter().map(|(_, changes)| changes).sum::<u64>();
+    let rank = |label: &str| {
+        priority
+            .iter()
+            .position(|p| p == label)
+            .unwrap_or(priority.len())
+    };
+    label_paths
+        .iter()
+        .map(|(label, globs)| {
+            let covered = files
+                .iter()
+                .filter(|(path, _)| util::glob_matches(globs, path))
+                .map(|(_, changes)| changes)
+                .sum::<u64>();
+            (label, covered)
+        })
+        .filter(|(_, covered)| 2 * covered > total)
+        .min_by(|(l1, c1), (l2, c2)| c2.cmp(c1).then(rank(l1).cmp(&rank(l2))).then(l1.cmp(l2)))
+        .map(|(label, _)| label.to_string())
+}
+
+/// A labeled or unlabeled event of a pull request.
+struct LabelEvent {
+    label: String,
+    by_bot: bool,
+}
+
+/// Return the labels to add and to remove, to get from the current labels to the wanted ones.
+/// Only labels managed by the config are touched, and only if the bot added them. Labels that a
+/// human added or removed last are left alone, and no label is added while a human added one of
+/// the managed labels.
+fn sync_labels(
+    current: &[String],
+    events: &[LabelEvent],
+    managed: &std::collections::HashSet<&str>,
+    wanted: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let last_by_human = |label: &str| {
+        events
+            .iter()
+            .rfind(|e| e.label == label)
+            .is_some_and(|e| !e.by_bot)
+    };
+    let remove = current
+        .iter()
+        .filter(|l| managed.contains(l.as_str()) && !wanted.contains(l) && !last_by_human(l))
+        .cloned()
+        .collect::<Vec<_>>();
+    let human_topic = current
+        .iter()
+        .any(|l| managed.contains(l.as_str()) && last_by_human(l));
+    let add = if human_topic {
+        Vec::new()
+    } else {
+        wanted
+            .iter()
+            .filter(|l| !current.contains(l) && !last_by_human(l))
+            .cloned()
+            .collect()
+    };
+    (add, remove)
+}
+
+async fn get_label_events(
+    github: &octocrab::Octocrab,
+    repo_slug: &str,
+    number: u64,
+    bot_username: &str,
+) -> Result<Vec<LabelEvent>> {
+    Ok(github
+        .all_pages(
+            github
+                .get::<octocrab::Page<serde_json::Value>, _, _>(
+                    format!("/repos/{repo_slug}/issues/{number}/events"),
+                    Some(&serde_json::json!({ "per_page": 100 })),
+                )
+                .await?,
+        )
+        .await?
+        .into_iter()
+        .filter(|e| e["event"] == "labeled" || e["event"] == "unlabeled")
+        .filter_map(|e| {
+            Some(LabelEvent {
+                label: e["label"]["name"].as_str()?.to_string(),
+                by_bot: e["actor"]["login"] == bot_username,
+            })
+        })
+        .collect())
+}
+
 async fn apply_labels_one(
     github: &octocrab::Octocrab,
     issues_api: &octocrab::issues::IssueHandler<'_>,
     config_repo: &crate::config::Repo,
     base_name: &str,
     pull: &octocrab::models::pulls::PullRequest,
+    bot_username: &str,
     dry_run: bool,
 ) -> Result<()> {
     let regs = config_repo.repo_labels.iter().fold(
@@ -120,15 +314,29 @@ async fn apply_labels_one(
     let pull_title = pull_title_trimmed;
     let labels = github
         .all_pages(issues_api.list_labels_for_issue(pull.number).send().await?)
-        .await?;
-    if !labels.is_empty() {
-        return Ok(());
-    }
+        .await?
+        .into_iter()
+        .map(|l| l.name)
+        .collect::<Vec<_>>();
     let mut new_labels = Vec::new();
-    if pull.base.ref_field != base_name {
-        if let Some(bl) = &config_repo.backport_label {
-            new_labels.push(bl.to_string());
-        }
+    let release_branches = config_repo
+        .backport_branch_labels
+        .keys()
+        .map(|b| b.as_str())
+        .collect::<Vec<_>>();
+    if let Some(branch) = backport_branch(
+        &pull.base.ref_field,
+        base_name,
+        pull_title,
+        &release_branches,
+    ) {
+        new_labels.extend(
+            config_repo
+                .backport_branch_labels
+                .get(branch)
+                .or(config_repo.backport_label.as_ref())
+                .cloned(),
+        );
     } else {
         for (label_name, title_regs) in regs {
             if title_regs.iter().any(|r| r.is_match(pull_title)) {
@@ -136,13 +344,177 @@ async fn apply_labels_one(
                 break;
             }
         }
+        if new_labels.is_empty() && !config_repo.repo_label_paths.is_empty() {
+            let files = github
+                .all_pages(
+                    github
+                        .get::<octocrab::Page<serde_json::Value>, _, _>(
+                            format!(
+                                "/repos/{slug}/pulls/{num}/files",
+                                slug = config_repo.repo_slug,
+                                num = pull.number
+                            ),
+                            Some(&serde_json::json!({ "per_page": 100 })),
+                        )
+                        .await?,
+                )
+                .await?
+                .into_iter()
+                .filter_map(|f| {
+                    Some((
+                        f["filename"].as_str()?.to_string(),
+                        f["changes"].as_u64().unwrap_or_default(),
+                    ))
+                })
+                .collect::<Vec<_>>();
+            new_labels.extend(pick_label_by_paths(
+                &files,
+                &config_repo.repo_label_paths,
+                &config_repo.repo_label_priority,
+            ));
+        }
+    }
+    let managed = config_repo
+        .repo_labels
+        .keys()
+        .chain(config_repo.repo_label_paths.keys())
+        .chain(config_repo.backport_label.iter())
+        .chain(config_repo.backport_branch_labels.values())
+        .map(|l| l.as_str())
+        .collect();
+    let events =
+        get_label_events(github, &config_repo.repo_slug, pull.number, bot_username).await?;
+    let (add, remove) = sync_labels(&labels, &events, &managed, &new_labels);
+    for label in &remove {
+        println!(" ... remove_label({label})");
+        if !dry_run {
+            issues_api.remove_label(pull.number, label).await?;
+        }
     }
-    if new_labels.is_empty() {
+    if add.is_empty() {
         return Ok(());
     }
-    println!(" ... add_to_labels({new_labels:?})");
+    println!(" ... add_to_labels({add:?})");
     if !dry_run {
-        issues_api.add_labels(pull.number, &new_labels).await?;
+        issues_api.add_labels(pull.number, &add).await?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_label_by_paths() {
+        let label_paths = std::collections::HashMap::from([
+            ("Wallet".to_string(), vec!["src/wallet/**".to_string()]),
+            (
+                "Tests".to_string(),
+                vec!["src/wallet/test/**".to_string(), "test/**".to_string()],
+            ),
+            ("RPC".to_string(), vec!["src/rpc/**".to_string()]),
+        ]);
+        let files = |f: &[(&str, u64)]| {
+            f.iter()
+                .map(|(p, c)| (p.to_string(), *c))
+                .collect::<Vec<_>>()
+        };
+        let priority = vec!["Tests".to_string()];
+        assert_eq!(
+            pick_label_by_paths(
+                &files(&[("src/wallet/wallet.cpp", 30), ("src/rpc/misc.cpp", 10)]),
+                &label_paths,
+                &priority,
+            ),
+            Some("Wallet".to_string())
+        );
+        // No majority
+        assert_eq!(
+            pick_label_by_paths(
+                &files(&[("src/wallet/wallet.cpp", 10), ("src/rpc/misc.cpp", 10)]),
+                &label_paths,
+                &priority,
+            ),
+            None
+        );
+        // Tie, broken by priority
+        assert_eq!(
+            pick_label_by_paths(
+                &files(&[("src/wallet/test/util.cpp", 10)]),
+                &label_paths,
+                &priority,
+            ),
+            Some("Tests".to_string())
+        );
+        assert_eq!(
+            pick_label_by_paths(
+                &files(&[("src/wallet/test/util.cpp", 10)]),
+                &label_paths,
+                &[]
+            ),
+            Some("Tests".to_string())
+        );
+    }
+
+    #[test]
+    fn test_backport_branch() {
+        let release = ["30.x", "29.x"];
+        assert_eq!(
+            backport_branch("30.x", "master", "Foo", &release),
+            Some("30.x")
+        );
+        assert_eq!(
+            backport_branch("master", "master", "[29.x] wallet: Foo", &release),
+            Some("29.x")
+        );
+        assert_eq!(
+            backport_branch("master", "master", "[WIP] wallet: Foo", &release),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sync_labels() {
+        let managed = std::collections::HashSet::from(["Wallet", "RPC/REST/ZMQ", "Backport"]);
+        let labels = |l: &[&str]| l.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let event = |label: &str, by_bot: bool| LabelEvent {
+            label: label.to_string(),
+            by_bot,
+        };
+        // Replace a stale bot label, and keep unmanaged labels
+        assert_eq!(
+            sync_labels(
+                &labels(&["Wallet", "Needs rebase"]),
+                &[event("Wallet", true), event("Needs rebase", false)],
+                &managed,
+                &labels(&["RPC/REST/ZMQ"]),
+            ),
+            (labels(&["RPC/REST/ZMQ"]), labels(&["Wallet"]))
+        );
+        // Never touch a label added by a human
+        assert_eq!(
+            sync_labels(
+                &labels(&["Wallet"]),
+                &[
+                    event("Wallet", true),
+                    event("Wallet", false),
+                    event("Wallet", false)
+                ],
+                &managed,
+                &labels(&["RPC/REST/ZMQ"]),
+            ),
+            (labels(&[]), labels(&[]))
+        );
+        // Do not re-add a label removed by a human
+        assert_eq!(
+            sync_labels(
+                &labels(&[]),
+                &[event("Backport", true), event("Backport", false)],
+                &managed,
+                &labels(&["Backport"]),
+            ),
+            (labels(&[]), labels(&[]))
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::{DrahtBotError, Result};
+use crate::Context;
+use crate::GitHubEvent;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use util::llm::{LlmExample, LlmExchange, LlmVerdict};
+
+pub struct LlmFeedbackFeature {
+    meta: FeatureMeta,
+}
+
+impl LlmFeedbackFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "LLM Feedback",
+                "Collect 👍/👎 reactions and '@DrahtBot llm wrong' replies on LLM outputs as a labelled dataset for llm_eval, if enabled in the yaml config.",
+                vec![
+                    GitHubEvent::IssueComment,
+                    GitHubEvent::Issues,
+                    GitHubEvent::PullRequest,
+                    GitHubEvent::PullRequestReview,
+                    GitHubEvent::PullRequestReviewComment,
+                ],
+            ),
+        }
+    }
+}
+
+/// The GitHub comment that an LLM output was posted in.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmComment {
+    Issue(u64),
+    /// An inline review comment.
+    Review(u64),
+    /// A section of the shared metadata comment, by its name in the layout config.
+    Section {
+        id: u64,
+        section: String,
+    },
+}
+
+impl LlmComment {
+    fn section(&self) -> Option<&str> {
+        match self {
+            Self::Section { section, .. } => Some(section),
+            _ => None,
+        }
+    }
+}
+
+/// An LLM output that was posted, but did not receive any feedback yet.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingLlmOutput {
+    repo: String,
+    number: u64,
+    comment: LlmComment,
+    kind: String,
+    url: String,
+    created_at: String,
+    #[serde(flatten)]
+    exchange: LlmExchange,
+}
+
+fn pending_path(dir: &Path) -> PathBuf {
+    dir.join("pending.jsonl")
+}
+
+fn dataset_path(dir: &Path) -> PathBuf {
+    dir.join("dataset.jsonl")
+}
+
+fn read_pending(dir: &Path) -> Result<Vec<PendingLlmOutput>> {
+    let path = pending_path(dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<std::result::Result<_, _>>()?)
+}
+
+fn write_pending(dir: &Path, pending: &[PendingLlmOutput]) -> Result<()> {
+    let mut text = String::new();
+    for p in pending {
+        text += &serde_json::to_string(p)?;
+        text += "\n";
+    }
+    // Write to a temporary file first, to not lose the pending outputs on a crash
+    let tmp = dir.join("pending.jsonl.tmp");
+    std::fs::write(&tmp, text)?;
+    std::fs::rename(tmp, pending_path(dir))?;
+    Ok(())
+}
+
+/// Add the outputs to the pending ones, skipping those already pending for the same pull request
+/// and kind. Earlier outputs of the same section are dropped, because they are no longer shown.
+fn add_pending(
+    all: &mut Vec<PendingLlmOutput>,
+    repo: &str,
+    number: u64,
+    comment: &LlmComment,
+    kind: &str,
+    url: &str,
+    exchanges: &[LlmExchange],
+) {
+    let same_pull = |p: &PendingLlmOutput| p.repo == repo && p.number == number;
+    if let Some(section) = comment.section() {
+        all.retain(|p| {
+            !(same_pull(p)
+                && p.comment.section() == Some(section)
+                && !exchanges.iter().any(|e| e.reply == p.exchange.reply))
+        });
+    }
+    let created_at = chrono::Utc::now().to_rfc3339();
+    for exchange in exchanges {
+        if all
+            .iter()
+            .any(|p| same_pull(p) && p.kind == kind && p.exchange.reply == exchange.reply)
+        {
+            continue;
+        }
+        all.push(PendingLlmOutput {
+            repo: repo.to_string(),
+            number,
+            comment: comment.clone(),
+            kind: kind.to_string(),
+            url: url.to_string(),
+            created_at: created_at.clone(),
+            exchange: exchange.clone(),
+        });
+    }
+}
+
+/// Remember the LLM outputs that were posted in the given comment, to collect feedback on them
+/// later. Does nothing, unless enabled in the config. Errors are only logged, so that the caller
+/// can continue.
+pub async fn record(
+    ctx: &Context,
+    repo: &str,
+    number: u64,
+    comment: LlmComment,
+    kind: &str,
+    url: &str,
+    exchanges: &[LlmExchange],
+) {
+    let Some(config) = &ctx.config.llm.feedback else {
+        return;
+    };
+    println!(" ... Record {kind} LLM output for feedback ({url})");
+    if ctx.dry_run {
+        return;
+    }
+    let _guard = ctx.llm_feedback_lock.lock().await;
+    let res = std::fs::create_dir_all(&config.dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            let mut all = read_pending(&config.dir)?;
+            add_pending(&mut all, repo, number, &comment, kind, url, exchanges);
+            write_pending(&config.dir, &all)
+        });
+    if let Err(err) = res {
+        println!(" ... ERROR when recording LLM output {:?}", err);
+    }
+}
+
+/// Return the text after the request to mark an LLM output as wrong, if the comment has one.
+fn llm_wrong_arg(body: &str, bot_username: &str) -> Option<String> {
+    let body = body.to_lowercase();
+    let command = format!("@{} llm wrong", bot_username.to_lowercase());
+    body.find(&command)
+        .map(|pos| body[pos + command.len()..].to_string())
+}
+
+/// Return the positions of the issue comment outputs that an "llm wrong" reply refers to: The
+/// latest outputs of the section or kind named after the command, or else the outputs containing a
+/// quoted line, or else the latest outputs of the thread.
+fn llm_wrong_targets(outputs: &[&PendingLlmOutput], body: &str, arg: &str) -> Vec<usize> {
+    // The outputs in the comment or section of the newest output. Outputs of a section that are
+    // no longer shown were already dropped when recording.
+    let latest = |pos: Vec<usize>| {
+        let newest = pos.iter().max_by_key(|i| &outputs[**i].created_at).copied();
+        pos.into_iter()
+            .filter(|i| newest.is_some_and(|n| outputs[*i].comment == outputs[n].comment))
+            .collect::<Vec<_>>()
+    };
+    let all = (0..outputs.len()).collect::<Vec<_>>();
+    if let Some(name) = arg.split_whitespace().next() {
+        let named = all
+            .iter()
+            .copied()
+            .filter(|i| outputs[*i].comment.section() == Some(name) || outputs[*i].kind == name)
+            .collect::<Vec<_>>();
+        if !named.is_empty() {
+            return latest(named);
+        }
+    }
+    let quoted = body
+        .lines()
+        .filter_map(|l| l.trim_start().strip_prefix('>'))
+        .map(|l| l.trim())
+        .filter(|l| l.chars().any(char::is_alphanumeric))
+        .collect::<Vec<_>>();
+    let found = all
+        .iter()
+        .copied()
+        .filter(|i| {
+            quoted
+                .iter()
+                .any(|q| outputs[*i].exchange.reply.contains(q))
+        })
+        .collect::<Vec<_>>();
+    if !found.is_empty() {
+        return found;
+    }
+    latest(all)
+}
+
+/// Return the verdict by majority of the 👍/👎 reactions of humans, or None if there is no
+/// majority. Returns an error if the comment no longer exists.
+async fn get_reaction_verdict(
+    ctx: &Context,
+    repo: &str,
+    comment: &LlmComment,
+) -> Result<Option<LlmVerdict>> {
+    let route = match comment {
+        LlmComment::Issue(id) => format!("/repos/{repo}/issues/comments/{id}/reactions"),
+        LlmComment::Review(id) => format!("/repos/{repo}/pulls/comments/{id}/reactions"),
+        // Reactions on the shared metadata comment can not be attributed to a section
+        LlmComment::Section { .. } => return Ok(None),
+    };
+    let reactions = ctx
+        .octocrab
+        .all_pages(
+            ctx.octocrab
+                .get::<octocrab::Page<serde_json::Value>, _, _>(
+                    route,
+                    Some(&serde_json::json!({ "per_page": 100 })),
+                )
+                .await?,
+        )
+        .await?;
+    let humans = reactions
+        .iter()
+        .filter(|r| r["user"]["login"] != ctx.bot_username.as_str())
+        .filter(|r| r["user"]["type"] != "Bot");
+    let count = |content: &str| humans.clone().filter(|r| r["content"] == content).count();
+    let (up, down) = (count("+1"), count("-1"));
+    Ok(match up.cmp(&down) {
+        std::cmp::Ordering::Greater => Some(LlmVerdict::Good),
+        std::cmp::Ordering::Less => Some(LlmVerdict::Bad),
+        std::cmp::Ordering::Equal => None,
+    })
+}
+
+#[async_trait]
+impl Feature for LlmFeedbackFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let Some(config) = &ctx.config.llm.feedback else {
+            return Ok(());
+        };
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let repo = payload["repository"]["full_name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+        let Some(number) = payload["issue"]["number"]
+            .as_u64()
+            .or(payload["pull_request"]["number"].as_u64())
+        else {
+            return Ok(());
+        };
+
+        // Held until the pending outputs are written back, so that none recorded meanwhile are lost
+        let _guard = ctx.llm_feedback_lock.lock().await;
+        let all = read_pending(&config.dir)?;
+        let thread = |p: &PendingLlmOutput| p.repo == repo && p.number == number;
+
+        // An explicit reply marks the outputs in the replied-to inline comment, or the outputs of
+        // the thread it refers to.
+        let mut wrong = Vec::new();
+        let comment = &payload["comment"];
+        let wrong_arg = llm_wrong_arg(
+            comment["body"].as_str().unwrap_or_default(),
+            &ctx.bot_username,
+        );
+        if let (Some(arg), "created", true) = (
+            wrong_arg,
+            action,
+            comment["user"]["login"] != ctx.bot_username.as_str(),
+        ) {
+            match event {
+                GitHubEvent::IssueComment => {
+                    let pos = (0..all.len())
+                        .filter(|i| thread(&all[*i]))
+                        .filter(|i| !matches!(all[*i].comment, LlmComment::Review(_)))
+                        .collect::<Vec<_>>();
+                    let outputs = pos.iter().map(|i| &all[*i]).collect::<Vec<_>>();
+                    wrong.extend(
+                        llm_wrong_targets(
+                            &outputs,
+                            comment["body"].as_str().unwrap_or_default(),
+                            &arg,
+                        )
+                        .into_iter()
+                        .map(|i| pos[i]),
+                    );
+                }
+                GitHubEvent::PullRequestReviewComment => {
+                    if let Some(id) = comment["in_reply_to_id"].as_u64() {
+                        wrong.extend((0..all.len()).filter(|i| {
+                            thread(&all[*i]) && all[*i].comment == LlmComment::Review(id)
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Reactions do not trigger webhooks, so they are only read once, when the pull request is
+        // closed or the output reached the max age.
+        let max_age = chrono::Duration::days(config.max_age_days as i64);
+        let is_due = |p: &PendingLlmOutput| {
+            (action == "closed" && thread(p))
+                || chrono::DateTime::parse_from_rfc3339(&p.created_at)
+                    .is_ok_and(|created| chrono::Utc::now() - created.to_utc() > max_age)
+        };
+        if wrong.is_empty() && !all.iter().any(is_due) {
+            return Ok(());
+        }
+        println!(
+            "Handling: {repo} {event}::{action} ({feature_name})",
+            feature_name = self.meta().name()
+        );
+
+        let mut remaining = Vec::new();
+        for (i, p) in all.into_iter().enumerate() {
+            let verdict = if wrong.contains(&i) {
+                Some(LlmVerdict::Bad)
+            } else if !is_due(&p) {
+                remaining.push(p);
+                continue;
+            } else {
+                match get_reaction_verdict(ctx, &p.repo, &p.comment).await {
+                    Ok(verdict) => verdict,
+                    Err(err) => {
+                        println!(" ... Drop LLM output of {}: {:?}", p.url, err);
+                        continue;
+                    }
+                }
+            };
+            match verdict {
+                Some(verdict) => {
+                    println!(" ... Label LLM output of {} as {:?}", p.url, verdict);
+                    if !ctx.dry_run {
+                        util::llm::append_llm_example(
+                            &dataset_path(&config.dir),
+                            &LlmExample {
+                                kind: p.kind,
+                                url: p.url,
+                                created_at: p.created_at,
+                                exchange: p.exchange,
+                                verdict,
+                            },
+                        )?;
+                    }
+                }
+                None if p.comment.section().is_some() => {
+                    println!(
+                        " ... Drop LLM output of {} without a reply, reactions on the metadata comment are not collected",
+                        p.url
+                    );
+                }
+                None => {
+                    println!(" ... Drop LLM output of {} without feedback", p.url);
+                }
+            }
+        }
+
+        if !ctx.dry_run {
+            write_pending(&config.dir, &remaining)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_llm_wrong() {
+        let exchange = |reply: &str| LlmExchange {
+            model: "m".to_string(),
+            messages: Vec::new(),
+            reply: reply.to_string(),
+        };
+        let section = |section: &str| LlmComment::Section {
+            id: 1,
+            section: section.to_string(),
+        };
+        let mut all = Vec::new();
+        add_pending(
+            &mut all,
+            "o/r",
+            1,
+            &section("overview"),
+            "overview",
+            "u",
+            &[exchange("a")],
+        );
+        add_pending(
+            &mut all,
+            "o/r",
+            1,
+            &section("llm_linter"),
+            "lint",
+            "u",
+            &[exchange("b"), exchange("c x")],
+        );
+        // An unchanged output is not added again, and a changed one replaces the previous
+        add_pending(
+            &mut all,
+            "o/r",
+            1,
+            &section("llm_linter"),
+            "lint",
+            "u",
+            &[exchange("b")],
+        );
+        add_pending(
+            &mut all,
+            "o/r",
+            1,
+            &section("overview"),
+            "overview",
+            "u",
+            &[exchange("d")],
+        );
+        let replies = all
+            .iter()
+            .map(|p| p.exchange.reply.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(replies, vec!["b", "d"]);
+
+        add_pending(
+            &mut all,
+            "o/r",
+            1,
+            &section("llm_linter"),
+            "lint",
+            "u",
+            &[exchange("b"), exchange("c x")],
+        );
+        let outputs = all.iter().collect::<Vec<_>>();
+        assert_eq!(
+            llm_wrong_arg("@DrahtBot LLM wrong lint", "DrahtBot").as_deref(),
+            Some(" lint")
+        );
+        assert_eq!(llm_wrong_arg("llm wrong", "DrahtBot"), None);
+        assert_eq!(llm_wrong_targets(&outputs, "", " overview"), vec![1]);
+        assert_eq!(llm_wrong_targets(&outputs, "", " llm_linter"), vec![0, 2]);
+        assert_eq!(
+            llm_wrong_targets(&outputs, "> c x\n\n@DrahtBot llm wrong", ""),
+            vec![2]
+        );
+    }
+}
@@ -1,5 +1,8 @@
 pub mod ci_status;
+pub mod commit_checks;
+pub mod commit_hygiene;
 pub mod labels;
+pub mod llm_feedback;
 pub mod spam_detection;
 pub mod summary_comment;
 
@@ -1,3 +1,4 @@
+use super::llm_feedback::{self, LlmComment};
 use super::{Feature, FeatureMeta};
 use crate::errors::{DrahtBotError, Result};
 use crate::Context;
@@ -88,12 +89,13 @@ impl Feature for SpamDetectionFeature {
                         .as_str()
                         .unwrap_or("[the pull request body is empty]"); // Missing body is an empty string
                     spam_llm(
+                        ctx,
                         &issues_api,
+                        repo_user,
+                        repo_name,
                         title,
                         body,
                         pr_number,
-                        &ctx.llm_token,
-                        ctx.dry_run,
                     )
                     .await?;
                 }
@@ -114,12 +116,13 @@ impl Feature for SpamDetectionFeature {
                         .as_str()
                         .unwrap_or("[the issue body is empty]"); // Missing body is an empty string
                     spam_llm(
+                        ctx,
                         &issues_api,
+                        repo_user,
+                        repo_name,
                         title,
                         body,
                         issue_number,
-                        &ctx.llm_token,
-                        ctx.dry_run,
                     )
                     .await?;
                 }
@@ -131,16 +134,16 @@ impl Feature for SpamDetectionFeature {
 }
 
 async fn spam_llm(
+    ctx: &Context,
     issues_api: &octocrab::issues::IssueHandler<'_>,
+    repo_user: &str,
+    repo_name: &str,
     title: &str,
     body: &str,
     issue_number: u64,
-    llm_token: &str,
-    dry_run: bool,
 ) -> Result<()> {
-    let llm_res = get_llm_result(title, body, llm_token)
-        .await
-        .unwrap_or("NORMAL".to_string());
+    let llm_exchange = get_llm_result(title, body, &ctx.llm.spam).await.ok();
+    let llm_res = llm_exchange.as_ref().map_or("NORMAL", |e| e.reply.as_str());
     if llm_res.starts_with("SPAM") {
         println!(
             "{} detected as likely spam with title={title}",
@@ -159,8 +162,20 @@ Generally, please focus on creating high-quality, original content that demonstr
 understanding of the project's requirements and goals.
 "#
             );
-            if !dry_run {
-                issues_api.create_comment(issue_number, reason).await?;
+            if !ctx.dry_run {
+                let comment = issues_api.create_comment(issue_number, reason).await?;
+                if let Some(exchange) = &llm_exchange {
+                    llm_feedback::record(
+                        ctx,
+                        &format!("{repo_user}/{repo_name}"),
+                        issue_number,
+                        LlmComment::Issue(comment.id.into_inner()),
+                        "spam",
+                        comment.html_url.as_str(),
+                        std::slice::from_ref(exchange),
+                    )
+                    .await;
+                }
                 issues_api
                     .update(issue_number)
                     .title(".")
@@ -180,25 +195,8 @@ understanding of the project's requirements and goals.
     Ok(())
 }
 
-async fn get_llm_result(title: &str, body: &str, llm_token: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    println!(" ... Run LLM check for spam detection.");
-    let question = format!(
-        r#"
-title: {title}
-body: {body}
-"#
-    );
-    let payload = serde_json::json!({
-      "model": "gpt-5.4-nano",
-      "messages": [
-        {
-          "role": "developer",
-          "content": [
-            {
-              "type": "text",
-              "text":
-r#"
+/// Prompt to classify an issue or pull request as SPAM or NORMAL.
+const LLM_PROMPT_SPAM: &str = r#"
 Detect whether the provided GitHub issue or pull request is SPAM or NORMAL based on its content.
 Start your reply with either SPAM or NORMAL. If you claim SPAM, you must include an explanation in English.
 Do not include any direct or verbatim references to the spam content (Promotional names, websites, services, ...)
@@ -238,43 +236,26 @@ Output:
 SPAM. This issue references a cryptocurrency which is unrelated to Bitcoin. It appears to be a placeholder or generic template with no real context or content, lacking any meaningful description, problem statement, or feature request related to the Bitcoin project.
 
 **Objective Reminder:** Classify GitHub issues and pull requests as either SPAM or NORMAL.
+"#;
+
+async fn get_llm_result(
+    title: &str,
+    body: &str,
+    llm: &util::llm::LlmClient,
+) -> Result<util::llm::LlmExchange> {
+    println!(" ... Run LLM check for spam detection.");
+    let question = format!(
+        r#"
+title: {title}
+body: {body}
 "#
-    }
-          ]
-        },
-        {
-          "role": "user",
-          "content": [
-            {
-              "type": "text",
-              "text": question
-              }
-          ]
-        }
-      ],
-      "response_format": {
-        "type": "text"
-      },
-      "verbosity": "low",
-      "reasoning_effort": "medium",
-      "service_tier": "default",
-      "store": true
-    });
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", llm_token))
-        .json(&payload)
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<serde_json::Value>()
-        .await?;
-    let text = response["choices"][0]["message"]["content"]
-        .as_str()
-        .ok_or(DrahtBotError::KeyNotFound)?
-        .to_string();
-    Ok(text)
+    );
+    Ok(llm
+        .exchange(vec![
+            util::llm::LlmMessage::system(LLM_PROMPT_SPAM),
+            util::llm::LlmMessage::user(&question),
+        ])
+        .await?)
 }
 
 async fn spam_follow_up(
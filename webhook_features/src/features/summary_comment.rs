@@ -1,14 +1,18 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
+use super::llm_feedback::{self, LlmComment};
 use super::{Feature, FeatureMeta};
 use crate::errors::DrahtBotError;
 use crate::errors::Result;
+use crate::llm_lint;
+use crate::reviewers;
 use crate::Context;
 use crate::GitHubEvent;
 use async_trait::async_trait;
 use lazy_static::lazy_static;
 use regex::Regex;
-use util::{all_llm_checks, make_llm_payload};
+use util::all_llm_checks;
 
 pub struct SummaryCommentFeature {
     meta: FeatureMeta,
@@ -29,6 +33,7 @@ impl SummaryCommentFeature {
                     GitHubEvent::IssueComment,
                     GitHubEvent::PullRequest,
                     GitHubEvent::PullRequestReview,
+                    GitHubEvent::PullRequestReviewThread,
                 ],
             ),
         }
@@ -97,8 +102,9 @@ impl Feature for SummaryCommentFeature {
                     refresh_summary_comment(ctx, repo, pr_number, None).await?
                 }
             }
-            GitHubEvent::PullRequestReview => {
+            GitHubEvent::PullRequestReview | GitHubEvent::PullRequestReviewThread => {
                 // https://docs.github.com/en/webhooks/webhook-events-and-payloads?actionType=submitted#pull_request_review
+                // https://docs.github.com/en/webhooks/webhook-events-and-payloads?actionType=resolved#pull_request_review_thread
                 let pr_number = payload["pull_request"]["number"]
                     .as_u64()
                     .ok_or(DrahtBotError::KeyNotFound)?;
@@ -118,7 +124,10 @@ impl Feature for SummaryCommentFeature {
 
 const BOT_SKIP_TAG: &str = "<!--meta-tag:bot-skip-->";
 
-fn summary_comment_template(reviews: Vec<Review>) -> String {
+fn summary_comment_template(
+    reviews: Vec<Review>,
+    unresolved: &HashMap<String, Vec<ReviewThread>>,
+) -> String {
     let review_url = "https://github.com/bitcoin/bitcoin/blob/master/CONTRIBUTING.md#code-review";
     let ai_policy_url = "https://github.com/bitcoin/bitcoin/blob/master/doc/AI_POLICY.md";
     let mut comment = format!(
@@ -160,7 +169,18 @@ See [the guideline]({review_url}) and [AI policy]({ai_policy_url}) for informati
                     ack_type.as_str(),
                     users
                         .iter()
-                        .map(|(user, url, _)| format!("[{user}]({url})"))
+                        .map(|(user, url, date)| {
+                            let num_open = if *ack_type == AckType::Ack {
+                                count_unresolved_before(unresolved, user, *date)
+                            } else {
+                                0
+                            };
+                            if num_open > 0 {
+                                format!("[{user}]({url}) (⚠️ {num_open} unresolved)")
+                            } else {
+                                format!("[{user}]({url})")
+                            }
+                        })
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
@@ -174,9 +194,127 @@ See [the guideline]({review_url}) and [AI policy]({ai_policy_url}) for informati
         comment += "\n";
     }
 
+    if !unresolved.is_empty() {
+        let mut users = unresolved.iter().collect::<Vec<_>>();
+        users.sort_by_key(|(user, _)| user.to_lowercase());
+        comment += "\n";
+        comment += "<details><summary>Unresolved review threads</summary>\n\n";
+        comment += "| Reviewer | Threads | Nits |\n";
+        comment += "| -------- | ------- | ---- |\n";
+        for (user, threads) in users {
+            comment += &format!(
+                "| {user} | {num} | {nits} |\n",
+                num = threads.len(),
+                nits = threads.iter().filter(|t| t.nit).count(),
+            );
+        }
+        comment += "\n";
+        comment +=
+            "An ACK marked with ⚠️ was given while the reviewer still had unresolved threads.\n";
+        comment += "\n</details>\n";
+    }
+
     comment
 }
 
+/// Number of unresolved threads by the user that were opened before the given date.
+fn count_unresolved_before(
+    unresolved: &HashMap<String, Vec<ReviewThread>>,
+    user: &str,
+    date: chrono::DateTime<chrono::Utc>,
+) -> usize {
+    unresolved
+        .get(user)
+        .map(|threads| threads.iter().filter(|t| t.date < date).count())
+        .unwrap_or(0)
+}
+
+struct ReviewThread {
+    user: String,
+    resolved: bool,
+    nit: bool,
+    date: chrono::DateTime<chrono::Utc>,
+}
+
+const REVIEW_THREADS_QUERY: &str = r#"
+query($owner: String!, $name: String!, $number: Int!, $cursor: String) {
+  repository(owner: $owner, name: $name) {
+    pullRequest(number: $number) {
+      reviewThreads(first: 100, after: $cursor) {
+        pageInfo { hasNextPage endCursor }
+        nodes {
+          isResolved
+          comments(first: 1) { nodes { author { login } body createdAt } }
+        }
+      }
+    }
+  }
+}
+"#;
+
+async fn get_review_threads(
+    ctx: &Context,
+    repo: &Repository,
+    pr_number: u64,
+) -> Result<Vec<ReviewThread>> {
+    let mut threads = Vec::new();
+    let mut cursor = serde_json::Value::Null;
+    loop {
+        let response: serde_json::Value = ctx
+            .octocrab
+            .graphql(&serde_json::json!({
+                "query": REVIEW_THREADS_QUERY,
+                "variables": {
+                    "owner": repo.owner,
+                    "name": repo.name,
+                    "number": pr_number,
+                    "cursor": cursor,
+                },
+            }))
+            .await?;
+        if let Some(errors) = response.get("errors") {
+            println!(" ... ERROR when fetching review threads {errors}");
+            return Err(DrahtBotError::KeyNotFound.into());
+        }
+        let page = &response["data"]["repository"]["pullRequest"]["reviewThreads"];
+        for node in page["nodes"].as_array().ok_or(DrahtBotError::KeyNotFound)? {
+            let first = &node["comments"]["nodes"][0];
+            let Some(user) = first["author"]["login"].as_str() else {
+                // Deleted (ghost) user
+                continue;
+            };
+            threads.push(ReviewThread {
+                user: user.to_string(),
+                resolved: node["isResolved"]
+                    .as_bool()
+                    .ok_or(DrahtBotError::KeyNotFound)?,
+                nit: is_nit(first["body"].as_str().unwrap_or_default()),
+                date: first["createdAt"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?
+                    .parse()?,
+            });
+        }
+        if page["pageInfo"]["hasNextPage"].as_bool() != Some(true) {
+            break;
+        }
+        cursor = page["pageInfo"]["endCursor"].clone();
+    }
+    Ok(threads)
+}
+
+fn is_nit(body: &str) -> bool {
+    let body = body
+        .trim_start()
+        .trim_start_matches(['*', '_', '('])
+        .to_lowercase();
+    // Also match "nits" and "nitpick", but not words like "nitrogen"
+    ["nit", "nits", "nitpick"].iter().any(|n| {
+        body.strip_prefix(n)
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric()))
+    })
+}
+
 struct GitHubReviewComment {
     user: String,
     url: String,
@@ -200,14 +338,19 @@ async fn refresh_summary_comment(
         .all_pages(issues_api.list_comments(pr_number).send().await?)
         .await?;
 
-    let mut cmt = util::get_metadata_sections_from_comments(&all_comments, pr_number);
-
-    if let Some(config_repo) = ctx
+    let config_repo = ctx
         .config
         .repositories
         .iter()
-        .find(|r| r.repo_slug == format!("{}/{}", repo.owner, repo.name))
-    {
+        .find(|r| r.repo_slug == format!("{}/{}", repo.owner, repo.name));
+
+    let mut cmt = util::get_metadata_sections_from_comments(
+        &all_comments,
+        pr_number,
+        &util::MetaLayout::for_repo(&format!("{}/{}", repo.owner, repo.name)),
+    );
+
+    if let Some(config_repo) = config_repo {
         if config_repo.corecheck {
             let coverage = r#"
 ### Code Coverage & Benchmarks
@@ -227,33 +370,188 @@ For details see: https://corecheck.dev/{owner}/{repo}/pulls/{pull_num}.
         }
     }
 
+    let pushed = llm_diff_pr.is_some();
     if let Some(url) = llm_diff_pr {
-        let mut text = "".to_string();
-        match get_llm_check(&url, &ctx.llm_token).await {
-            Ok(issues) => {
-                if issues.is_empty() {
-                    // text remains empty
-                } else {
-                    text = format!(
-                        "\n### LLM Linter (✨ experimental)\n\n{issues}\n\n<sup>{date}</sup>\n",
-                        issues = issues.join("\n"),
-                        date = chrono::Utc::now().format("%F %H:%M:%S")
-                    );
+        // Only called on push events
+        cmt.set_state(
+            util::MetaStateKey::LastPush,
+            chrono::Utc::now().to_rfc3339().into(),
+        );
+        let default_lint = crate::config::LlmLint::default();
+        let lint = config_repo.map_or(&default_lint, |r| &r.llm_lint);
+        if let Some(config_repo) = config_repo.filter(|r| r.llm_overview) {
+            let overview_head = cmt
+                .state(util::MetaStateKey::OverviewHead)
+                .and_then(|h| h.as_str());
+            if overview_head != Some(pr.head.sha.as_str()) {
+                match get_llm_overview(ctx, &repo, &pr, &url, config_repo).await {
+                    Ok((text, exchange)) => {
+                        cmt.set_state(util::MetaStateKey::OverviewHead, pr.head.sha.clone().into());
+                        util::update_metadata_comment(
+                            &issues_api,
+                            &mut cmt,
+                            &text,
+                            util::IdComment::SecOverview,
+                            ctx.dry_run,
+                        )
+                        .await?;
+                        if let Some(id) = cmt.id {
+                            llm_feedback::record(
+                                ctx,
+                                &format!("{}/{}", repo.owner, repo.name),
+                                pr_number,
+                                LlmComment::Section {
+                                    id: id.into_inner(),
+                                    section: "overview".to_string(),
+                                },
+                                "overview",
+                                &format!(
+                                    "https://github.com/{}/{}/pull/{pr_number}#issuecomment-{id}",
+                                    repo.owner, repo.name
+                                ),
+                                std::slice::from_ref(&exchange),
+                            )
+                            .await;
+                        }
+                    }
+                    Err(err) => {
+                        println!(" ... ERROR when requesting llm overview {:?}", err);
+                    }
                 }
             }
-            Err(err) => {
-                println!(" ... ERROR when requesting llm check {:?}", err);
-                // text remains empty
+        }
+        // The file was validated on startup, so this can only fail after a runtime edit. Skip the
+        // LLM linter, but update the other sections.
+        let llm_checks = match config_repo.map(|r| r.llm_checks.load()) {
+            Some(Ok(checks)) => Some(checks),
+            Some(Err(err)) => {
+                println!(" ... ERROR when loading llm checks {:?}", err);
+                None
+            }
+            None => Some(all_llm_checks()),
+        };
+        if let Some(llm_checks) = llm_checks {
+            let mut text = "".to_string();
+            // The exchanges that resulted in the section text
+            let mut exchanges = Vec::new();
+            let inline = config_repo.is_some_and(|r| r.llm_inline_review);
+            if inline || lint.incremental {
+                match get_llm_findings(ctx, &repo, &cmt, &pr.head.sha, &url, &llm_checks, lint)
+                    .await
+                {
+                    Ok(linted) => {
+                        let findings = linted
+                            .iter()
+                            .filter_map(|l| {
+                                let llm_check = llm_checks.iter().find(|c| c.name == l.check)?;
+                                Some((llm_check.clone(), l.finding.clone(), l.exchange.clone()))
+                            })
+                            .collect::<Vec<_>>();
+                        let posted = if inline {
+                            llm_inline_review(ctx, &repo, pr_number, &pr.head.sha, &findings).await
+                        } else {
+                            Ok(0)
+                        };
+                        match posted {
+                            Ok(_) => {
+                                cmt.set_state(
+                                    util::MetaStateKey::LlmLintHead,
+                                    pr.head.sha.clone().into(),
+                                );
+                                if lint.incremental {
+                                    cmt.set_state(
+                                        util::MetaStateKey::LlmFindings,
+                                        serde_json::to_value(&linted)?,
+                                    );
+                                }
+                                if findings.is_empty() {
+                                    // text remains empty
+                                } else if inline {
+                                    text = format!(
+                                        "\n### LLM Linter (✨ experimental)\n\n{num_findings} possible issue(s) were posted as inline review comments.\n\n<sup>{date}</sup>\n",
+                                        num_findings = findings.len(),
+                                        date = chrono::Utc::now().format("%F %H:%M:%S")
+                                    );
+                                } else {
+                                    for e in findings.iter().filter_map(|(_, _, e)| e.as_ref()) {
+                                        if !exchanges.contains(&**e) {
+                                            exchanges.push((**e).clone());
+                                        }
+                                    }
+                                    text = format!(
+                                        "\n### LLM Linter (✨ experimental)\n\n{issues}\n\n<sup>{date}</sup>\n",
+                                        issues = llm_lint::render_findings(
+                                            findings.iter().map(|(c, f, _)| (c, f)),
+                                        ).join("\n"),
+                                        date = chrono::Utc::now().format("%F %H:%M:%S")
+                                    );
+                                }
+                            }
+                            Err(err) => {
+                                println!(" ... ERROR when posting llm review {:?}", err);
+                                // text remains empty
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        println!(" ... ERROR when requesting llm findings {:?}", err);
+                        // text remains empty
+                    }
+                }
+            } else {
+                let issues = match llm_lint::get_diff(&url).await {
+                    Ok(diff) => {
+                        llm_lint::get_llm_check(&diff, &ctx.llm.lint, &llm_checks, lint).await
+                    }
+                    Err(err) => Err(err),
+                };
+                match issues {
+                    Ok((issues, found_by)) => {
+                        cmt.set_state(util::MetaStateKey::LlmLintHead, pr.head.sha.clone().into());
+                        exchanges = found_by;
+                        if issues.is_empty() {
+                            // text remains empty
+                        } else {
+                            text = format!(
+                                "\n### LLM Linter (✨ experimental)\n\n{issues}\n\n<sup>{date}</sup>\n",
+                                issues = issues.join("\n"),
+                                date = chrono::Utc::now().format("%F %H:%M:%S")
+                            );
+                        }
+                    }
+                    Err(err) => {
+                        println!(" ... ERROR when requesting llm check {:?}", err);
+                        // text remains empty
+                    }
+                }
+            }
+            util::update_metadata_comment(
+                &issues_api,
+                &mut cmt,
+                &text,
+                util::IdComment::SecLmCheck,
+                ctx.dry_run,
+            )
+            .await?;
+            if let (Some(id), false) = (cmt.id, text.is_empty()) {
+                llm_feedback::record(
+                    ctx,
+                    &format!("{}/{}", repo.owner, repo.name),
+                    pr_number,
+                    LlmComment::Section {
+                        id: id.into_inner(),
+                        section: "llm_linter".to_string(),
+                    },
+                    "lint",
+                    &format!(
+                        "https://github.com/{}/{}/pull/{pr_number}#issuecomment-{id}",
+                        repo.owner, repo.name
+                    ),
+                    &exchanges,
+                )
+                .await;
             }
         }
-        util::update_metadata_comment(
-            &issues_api,
-            &mut cmt,
-            &text,
-            util::IdComment::SecLmCheck,
-            ctx.dry_run,
-        )
-        .await?;
     }
 
     let mut all_comments = all_comments
@@ -371,7 +669,104 @@ For details see: https://corecheck.dev/{owner}/{repo}/pulls/{pull_num}.
         .map(|r| r.user.clone())
         .collect::<Vec<_>>();
 
-    let comment = summary_comment_template(user_reviews);
+    let mut unresolved: HashMap<String, Vec<ReviewThread>> = HashMap::new();
+    match get_review_threads(ctx, &repo, pr_number).await {
+        Ok(threads) => {
+            for thread in threads
+                .into_iter()
+                .filter(|t| !t.resolved && t.user != pr_author)
+            {
+                unresolved
+                    .entry(thread.user.clone())
+                    .or_default()
+                    .push(thread);
+            }
+        }
+        Err(err) => {
+            println!(" ... ERROR when requesting review threads {:?}", err);
+        }
+    }
+
+    if let Some(config) = config_repo.and_then(|r| r.reviewer_suggestions.as_ref()) {
+        if pushed {
+            let mut exclude = user_reviews
+                .iter()
+                .map(|r| r.user.as_str())
+                .collect::<Vec<_>>();
+            exclude.push(&pr_author);
+            exclude.push(&ctx.bot_username);
+            match reviewers::get_reviewer_suggestions(
+                ctx,
+                &format!("{}/{}", repo.owner, repo.name),
+                pr_number,
+                &pr.base.ref_field,
+                &exclude,
+                config,
+            )
+            .await
+            {
+                Ok(suggestions) => {
+                    cmt.set_state(
+                        util::MetaStateKey::SuggestedReviewers,
+                        suggestions
+                            .iter()
+                            .map(|s| s.login.as_str())
+                            .collect::<Vec<_>>()
+                            .into(),
+                    );
+                    util::update_metadata_comment(
+                        &issues_api,
+                        &mut cmt,
+                        &reviewers::render_suggestions(&suggestions),
+                        util::IdComment::SecReviewerSuggestions,
+                        ctx.dry_run,
+                    )
+                    .await?;
+                }
+                Err(err) => {
+                    println!(" ... ERROR when suggesting reviewers {:?}", err);
+                }
+            }
+        }
+        let concept_acked = user_reviews.iter().any(|r| {
+            matches!(
+                r.ack_type,
+                AckType::ConceptAck | AckType::ApproachAck | AckType::Ack | AckType::StaleAck
+            )
+        });
+        let requested = cmt
+            .state(util::MetaStateKey::ReviewersRequested)
+            .and_then(|r| r.as_bool())
+            == Some(true);
+        if config.auto_request && concept_acked && !requested {
+            let suggested = cmt
+                .state(util::MetaStateKey::SuggestedReviewers)
+                .and_then(|s| s.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|s| s.as_str())
+                .filter(|s| !user_reviews.iter().any(|r| r.user == *s))
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>();
+            // Done one-by-one to work around https://github.com/maflcko/DrahtBot/issues/29
+            for reviewer in &suggested {
+                println!(" ... Request review from suggested {}", reviewer);
+                if ctx.dry_run {
+                    continue;
+                }
+                if let Err(err) = pulls_api
+                    .request_reviews(pr_number, [reviewer.to_string()], [])
+                    .await
+                {
+                    println!(" ... ERROR when requesting review {:?}", err);
+                }
+            }
+            // Written along with the reviews section below
+            cmt.set_state(util::MetaStateKey::ReviewersRequested, true.into());
+        }
+    }
+
+    let comment = summary_comment_template(user_reviews, &unresolved);
     util::update_metadata_comment(
         &issues_api,
         &mut cmt,
@@ -474,51 +869,238 @@ fn parse_review(comment: &str) -> Option<AckCommit> {
     None
 }
 
-async fn get_llm_check(llm_diff_pr: &str, llm_token: &str) -> Result<Vec<String>> {
-    let client = reqwest::Client::new();
-    println!(" ... Run LLM check.");
-    let diff = client
-        .get(llm_diff_pr)
-        .send()
+/// Return the overview section for the head of the pull request, along with the exchange for it.
+async fn get_llm_overview(
+    ctx: &Context,
+    repo: &Repository,
+    pr: &octocrab::models::pulls::PullRequest,
+    llm_diff_pr: &str,
+    config_repo: &crate::config::Repo,
+) -> Result<(String, util::llm::LlmExchange)> {
+    let commits = ctx
+        .octocrab
+        .all_pages(
+            ctx.octocrab
+                .get::<octocrab::Page<serde_json::Value>, _, _>(
+                    format!(
+                        "/repos/{}/{}/pulls/{}/commits",
+                        repo.owner, repo.name, pr.number
+                    ),
+                    Some(&serde_json::json!({ "per_page": 100 })),
+                )
+                .await?,
+        )
         .await?
-        .error_for_status()?
-        .text()
-        .await?;
+        .into_iter()
+        .map(|c| {
+            c["commit"]["message"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    let mut subjects = commits
+        .iter()
+        .map(|m| m.lines().next().unwrap_or_default())
+        .collect::<Vec<_>>();
+    subjects.extend(pr.title.as_deref());
+    let subsystems = crate::llm_overview::touched_subsystems(&config_repo.repo_labels, &subjects);
+    let diff = llm_lint::get_diff(llm_diff_pr).await?;
+    crate::llm_overview::get_llm_overview(
+        &diff,
+        &commits,
+        &subsystems,
+        &pr.head.sha,
+        &ctx.llm.lint,
+        &config_repo.llm_lint,
+    )
+    .await
+}
 
-    let diff = util::prepare_raw_diff_for_llm(&diff);
-    let mut issues = Vec::new();
-
-    for llm_check in all_llm_checks() {
-        let payload = make_llm_payload(&diff, llm_check.prompt());
-        let response = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", llm_token))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
-        let text = response["choices"][0]["message"]["content"]
-            .as_str()
-            .ok_or(DrahtBotError::KeyNotFound)?
-            .to_string();
-        if text.is_empty() {
-            println!("ERROR: empty llm response: {response}");
-            return Err(DrahtBotError::KeyNotFound.into());
+/// Return the LLM linter findings for the head of the pull request. When linting incrementally,
+/// only the changes since the last linted head are linted, and the previous findings are kept if
+/// their lines still exist.
+async fn get_llm_findings(
+    ctx: &Context,
+    repo: &Repository,
+    cmt: &util::MetaComment,
+    head_sha: &str,
+    llm_diff_pr: &str,
+    llm_checks: &[util::LlmCheck],
+    lint: &crate::config::LlmLint,
+) -> Result<Vec<llm_lint::LintedFinding>> {
+    let pr_diff = llm_lint::get_diff(llm_diff_pr).await?;
+    let diff_lines = util::parse_diff_right_side(&pr_diff);
+
+    let previous = match (
+        lint.incremental,
+        cmt.state(util::MetaStateKey::LlmLintHead)
+            .and_then(|h| h.as_str()),
+        cmt.state(util::MetaStateKey::LlmFindings),
+    ) {
+        (true, Some(head), Some(findings)) => {
+            serde_json::from_value::<Vec<llm_lint::LintedFinding>>(findings.clone())
+                .ok()
+                .map(|f| (head.to_string(), f))
         }
-        if text.contains(llm_check.magic_all_good) {
-            continue;
+        _ => None,
+    };
+
+    let mut diff = pr_diff.clone();
+    let mut linted = Vec::new();
+    if let Some((prev_head, prev_findings)) = previous {
+        let compare = ctx
+            .octocrab
+            .get::<serde_json::Value, _, ()>(
+                format!(
+                    "/repos/{}/{}/compare/{prev_head}...{head_sha}",
+                    repo.owner, repo.name
+                ),
+                None,
+            )
+            .await;
+        match compare
+            .ok()
+            .as_ref()
+            .and_then(llm_lint::interdiff_from_compare)
+        {
+            Some(interdiff) => {
+                println!(" ... Only lint the changes since {prev_head}.");
+                diff = interdiff;
+                linted = llm_lint::relocate_findings(prev_findings, &diff_lines);
+            }
+            None => {
+                println!(" ... Lint the full diff, because {prev_head} is not an ancestor.");
+            }
         }
-        let issue = format!(
-            "\n\n{topic}\n\n{text}\n\n",
-            topic = llm_check.topic,
-            text = text,
+    }
+
+    let found = if diff.is_empty() {
+        Vec::new()
+    } else {
+        llm_lint::get_llm_findings(&diff, &pr_diff, &ctx.llm.lint, llm_checks, lint).await?
+    };
+    for (llm_check, finding, exchange) in found {
+        let f = llm_lint::LintedFinding::new(&llm_check, finding, exchange, &diff_lines);
+        if !linted
+            .iter()
+            .any(|l| l.check == f.check && l.finding == f.finding)
+        {
+            linted.push(f);
+        }
+    }
+    Ok(linted)
+}
+
+/// Submit the LLM findings as one review with inline comments, skipping the ones that were
+/// already posted earlier. Returns the number of findings.
+async fn llm_inline_review(
+    ctx: &Context,
+    repo: &Repository,
+    pr_number: u64,
+    head_sha: &str,
+    findings: &[(
+        util::LlmCheck,
+        util::LlmFinding,
+        Option<Arc<util::llm::LlmExchange>>,
+    )],
+) -> Result<usize> {
+    let posted = ctx
+        .octocrab
+        .all_pages(
+            ctx.octocrab
+                .get::<octocrab::Page<serde_json::Value>, _, _>(
+                    format!(
+                        "/repos/{}/{}/pulls/{pr_number}/comments",
+                        repo.owner, repo.name
+                    ),
+                    Some(&serde_json::json!({ "per_page": 100 })),
+                )
+                .await?,
+        )
+        .await?
+        .into_iter()
+        .filter(|c| c["user"]["login"] == ctx.bot_username.as_str())
+        .map(|c| {
+            (
+                c["path"].as_str().unwrap_or_default().to_string(),
+                c["body"].as_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect::<std::collections::HashSet<_>>();
+
+    let comments = findings
+        .iter()
+        .map(|(check, f, _)| {
+            (
+                f,
+                format!("{topic}\n\n{msg}", topic = check.topic, msg = f.message),
+            )
+        })
+        .filter(|(f, body)| !posted.contains(&(f.file.clone(), body.clone())))
+        .map(|(f, body)| {
+            serde_json::json!({
+                "path": f.file,
+                "line": f.line,
+                "side": "RIGHT",
+                "body": body,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if !comments.is_empty() {
+        println!(
+            " ... Post LLM review with {} inline comments",
+            comments.len()
         );
-        issues.push(issue);
+        if !ctx.dry_run {
+            let review: serde_json::Value = ctx
+                .octocrab
+                .post(
+                    format!(
+                        "/repos/{}/{}/pulls/{pr_number}/reviews",
+                        repo.owner, repo.name
+                    ),
+                    Some(&serde_json::json!({
+                        "commit_id": head_sha,
+                        "event": "COMMENT",
+                        "body": "LLM Linter (✨ experimental): Possible issues found on the changed lines. These may be false positives.",
+                        "comments": comments,
+                    })),
+                )
+                .await?;
+            let review_comments = ctx
+                .octocrab
+                .get::<Vec<serde_json::Value>, _, _>(
+                    format!(
+                        "/repos/{}/{}/pulls/{pr_number}/reviews/{}/comments",
+                        repo.owner, repo.name, review["id"]
+                    ),
+                    Some(&serde_json::json!({ "per_page": 100 })),
+                )
+                .await?;
+            for c in review_comments {
+                let Some((_, _, Some(exchange))) = findings.iter().find(|(check, f, _)| {
+                    c["path"] == f.file.as_str()
+                        && c["body"] == format!("{}\n\n{}", check.topic, f.message)
+                }) else {
+                    continue;
+                };
+                llm_feedback::record(
+                    ctx,
+                    &format!("{}/{}", repo.owner, repo.name),
+                    pr_number,
+                    LlmComment::Review(c["id"].as_u64().ok_or(DrahtBotError::KeyNotFound)?),
+                    "lint_inline",
+                    c["html_url"].as_str().unwrap_or_default(),
+                    std::slice::from_ref(&**exchange),
+                )
+                .await;
+            }
+        }
     }
 
-    Ok(issues)
+    Ok(findings.len())
 }
 
 // Test that parse_review works
@@ -867,4 +1449,19 @@ mod tests {
             assert_eq!(actual, test_case.expected);
         }
     }
+
+    #[test]
+    fn test_is_nit() {
+        assert!(is_nit("nit: typo"));
+        assert!(is_nit("Nit: typo"));
+        assert!(is_nit("  **nit**: typo"));
+        assert!(is_nit("(nit) typo"));
+        assert!(is_nit("nitpick: typo"));
+        assert!(is_nit("nit"));
+        assert!(is_nit("nits:\n- typo"));
+        assert!(!is_nit("nitrogen is used here"));
+        assert!(!is_nit("nitpicking-free code"));
+        assert!(!is_nit("This is not a nit"));
+        assert!(!is_nit("Why is this needed?"));
+    }
 }
@@ -0,0 +1,317 @@
+use crate::config::LlmLint;
+use crate::errors::{DrahtBotError, Result};
+use futures::StreamExt;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use util::llm::{LlmClient, LlmExchange, LlmMessage};
+use util::{make_llm_messages, DiffLine, LlmCheck, LlmFinding};
+
+pub async fn get_diff(url: &str) -> Result<String> {
+    Ok(reqwest::Client::new()
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?)
+}
+
+/// Split the parts of the diff that each check should see into chunks. Returns the index of the
+/// check along with the chunk.
+fn chunk_diff(diff: &str, llm_checks: &[LlmCheck], lint: &LlmLint) -> Vec<(usize, String)> {
+    let diff = lint.filter_diff(diff);
+    let mut chunks = Vec::new();
+    for (i, llm_check) in llm_checks.iter().enumerate() {
+        let diff = llm_check.filter_diff(&diff);
+        for chunk in util::chunk_diff(&diff, lint.chunk_chars()) {
+            chunks.push((i, chunk));
+        }
+    }
+    chunks
+}
+
+/// Send all requests, at most `lint.parallel` at a time, and return the exchanges in order.
+async fn chat_all(
+    llm: &LlmClient,
+    requests: &[Vec<LlmMessage>],
+    lint: &LlmLint,
+) -> Result<Vec<LlmExchange>> {
+    println!(" ... Send {} LLM requests.", requests.len());
+    let mut replies = futures::stream::iter(requests.iter().cloned().enumerate())
+        .map(|(i, messages)| async move { (i, llm.exchange(messages).await) })
+        .buffer_unordered(lint.parallel.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    replies.sort_by_key(|(i, _)| *i);
+    Ok(replies
+        .into_iter()
+        .map(|(_, reply)| reply)
+        .collect::<std::result::Result<_, _>>()?)
+}
+
+/// Run the LLM checks and return the issues of each check, deduplicated by line, along with the
+/// exchanges that found them.
+pub async fn get_llm_check(
+    diff: &str,
+    llm: &LlmClient,
+    llm_checks: &[LlmCheck],
+    lint: &LlmLint,
+) -> Result<(Vec<String>, Vec<LlmExchange>)> {
+    println!(" ... Run LLM check.");
+    let chunks = chunk_diff(diff, llm_checks, lint);
+    let requests = chunks
+        .iter()
+        .map(|(i, chunk)| {
+            make_llm_messages(
+                &util::prepare_raw_diff_for_llm(chunk),
+                llm_checks[*i].prompt(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let replies = chat_all(llm, &requests, lint).await?;
+
+    let mut issues = Vec::new();
+    let mut exchanges = Vec::new();
+    for (i, llm_check) in llm_checks.iter().enumerate() {
+        let found = chunks
+            .iter()
+            .zip(replies.iter())
+            .filter(|((c, _), e)| *c == i && !e.reply.contains(&*llm_check.magic_all_good))
+            .map(|(_, e)| e)
+            .collect::<Vec<_>>();
+        exchanges.extend(found.iter().map(|e| (*e).clone()));
+        let mut seen = HashSet::new();
+        let text = found
+            .iter()
+            .flat_map(|e| e.reply.lines())
+            .filter(|line| !line.trim().is_empty() && seen.insert(line.trim()))
+            .collect::<Vec<_>>();
+        if text.is_empty() {
+            continue;
+        }
+        let issue = format!(
+            "\n\n{topic}\n\n{text}\n\n",
+            topic = llm_check.topic,
+            text = text.join("\n"),
+        );
+        issues.push(issue);
+    }
+
+    Ok((issues, exchanges))
+}
+
+/// Run the LLM checks on the diff and return the deduplicated findings that are on lines of the
+/// pull request diff, along with the exchange that found them. The diff may be a subset of the
+/// pull request diff.
+pub async fn get_llm_findings(
+    diff: &str,
+    pr_diff: &str,
+    llm: &LlmClient,
+    llm_checks: &[LlmCheck],
+    lint: &LlmLint,
+) -> Result<Vec<(LlmCheck, LlmFinding, Arc<LlmExchange>)>> {
+    println!(" ... Run LLM check for findings.");
+    let diff_lines = util::parse_diff_right_side(pr_diff);
+    let chunks = chunk_diff(diff, llm_checks, lint);
+    let requests = chunks
+        .iter()
+        .map(|(i, chunk)| {
+            make_llm_messages(
+                &util::prepare_numbered_diff_for_llm(chunk),
+                llm_checks[*i].prompt_findings(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let replies = chat_all(llm, &requests, lint).await?;
+
+    let mut seen = HashSet::new();
+    let mut findings = Vec::new();
+    for ((i, _), exchange) in chunks.iter().zip(replies) {
+        let llm_check = &llm_checks[*i];
+        let Some(found) = util::parse_llm_findings(&exchange.reply) else {
+            println!("ERROR: malformed llm findings: {}", exchange.reply);
+            return Err(DrahtBotError::KeyNotFound.into());
+        };
+        let exchange = Arc::new(exchange);
+        for finding in found {
+            if !diff_lines
+                .get(&finding.file)
+                .is_some_and(|lines| lines.contains_key(&finding.line))
+            {
+                println!(
+                    " ... Drop finding outside of the diff: {}:{}",
+                    finding.file, finding.line
+                );
+                continue;
+            }
+            if seen.insert((
+                *i,
+                finding.file.clone(),
+                finding.line,
+                finding.message.clone(),
+            )) {
+                findings.push((llm_check.clone(), finding, exchange.clone()));
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+/// A finding along with the text of its line, so that it can be found again after later pushes.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct LintedFinding {
+    pub check: String,
+    #[serde(flatten)]
+    pub finding: LlmFinding,
+    pub text: String,
+    /// The exchange that found it, if it was found in this run.
+    #[serde(skip)]
+    pub exchange: Option<Arc<LlmExchange>>,
+}
+
+impl LintedFinding {
+    pub fn new(
+        llm_check: &LlmCheck,
+        finding: LlmFinding,
+        exchange: Arc<LlmExchange>,
+        diff_lines: &BTreeMap<String, BTreeMap<u64, DiffLine>>,
+    ) -> Self {
+        let text = diff_lines
+            .get(&finding.file)
+            .and_then(|lines| lines.get(&finding.line))
+            .map(|l| l.text.clone())
+            .unwrap_or_default();
+        Self {
+            check: llm_check.name.to_string(),
+            finding,
+            text,
+            exchange: Some(exchange),
+        }
+    }
+}
+
+/// Move previous findings to the line with the same text in the new diff, preferring the
+/// closest one. Findings whose line no longer exists are dropped.
+pub fn relocate_findings(
+    previous: Vec<LintedFinding>,
+    diff_lines: &BTreeMap<String, BTreeMap<u64, DiffLine>>,
+) -> Vec<LintedFinding> {
+    previous
+        .into_iter()
+        .filter_map(|mut f| {
+            let line = diff_lines
+                .get(&f.finding.file)?
+                .iter()
+                .filter(|(_, l)| l.text == f.text)
+                .map(|(num, _)| *num)
+                .min_by_key(|num| num.abs_diff(f.finding.line))?;
+            f.finding.line = line;
+            Some(f)
+        })
+        .collect()
+}
+
+/// Return the diff between two commits from the response of the GitHub compare API, or None if
+/// the old commit is not an ancestor of the new one, for example after a rebase, or if the patch
+/// of a changed file is missing, for example because it is too large.
+pub fn interdiff_from_compare(compare: &serde_json::Value) -> Option<String> {
+    if compare["status"] != "ahead" && compare["status"] != "identical" {
+        return None;
+    }
+    let files = compare["files"].as_array()?;
+    // The API returns at most 300 files
+    if files.len() >= 300 {
+        return None;
+    }
+    let mut diff = String::new();
+    for file in files {
+        let path = file["filename"].as_str()?;
+        let Some(patch) = file["patch"].as_str() else {
+            if file["changes"].as_u64() == Some(0) {
+                // A rename or mode change without content changes
+                continue;
+            }
+            return None;
+        };
+        diff += &format!("diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n{patch}\n");
+    }
+    Some(diff)
+}
+
+/// Render the findings grouped by check, like the replies of get_llm_check.
+pub fn render_findings<'a>(
+    findings: impl IntoIterator<Item = (&'a LlmCheck, &'a LlmFinding)>,
+) -> Vec<String> {
+    let mut by_topic = Vec::<(&str, Vec<String>)>::new();
+    for (llm_check, f) in findings {
+        let line = format!("- `{}:{}`: {}", f.file, f.line, f.message);
+        match by_topic.iter_mut().find(|(t, _)| *t == llm_check.topic) {
+            Some((_, lines)) => lines.push(line),
+            None => by_topic.push((&llm_check.topic, vec![line])),
+        }
+    }
+    by_topic
+        .into_iter()
+        .map(|(topic, lines)| format!("\n\n{topic}\n\n{}\n\n", lines.join("\n")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relocate_findings() {
+        let diff = r#"diff --git a/a.cpp b/a.cpp
+--- a/a.cpp
++++ b/a.cpp
+@@ -1,2 +1,5 @@
++// new line
+ int a;
++int b; // tpyo
++int c;
++int b; // tpyo
+"#;
+        let finding = |line, text: &str| LintedFinding {
+            check: "typos".to_string(),
+            finding: LlmFinding {
+                file: "a.cpp".to_string(),
+                line,
+                message: "tpyo -> typo".to_string(),
+            },
+            text: text.to_string(),
+            exchange: None,
+        };
+        let relocated = relocate_findings(
+            vec![
+                finding(1, "int b; // tpyo"),
+                finding(6, "int b; // tpyo"),
+                finding(2, "int gone;"),
+                finding(2, "int a;"),
+            ],
+            &util::parse_diff_right_side(diff),
+        );
+        assert_eq!(
+            relocated,
+            vec![
+                finding(3, "int b; // tpyo"),
+                finding(5, "int b; // tpyo"),
+                finding(2, "int a;"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interdiff_from_compare() {
+        let compare = |files| serde_json::json!({ "status": "ahead", "files": files });
+        let patch = serde_json::json!({ "filename": "a.cpp", "changes": 1, "patch": "@@ -1 +1 @@\n-a\n+b" });
+        let renamed = serde_json::json!({ "filename": "b.cpp", "changes": 0 });
+        let large = serde_json::json!({ "filename": "c.cpp", "changes": 5000 });
+        assert_eq!(
+            interdiff_from_compare(&compare(vec![patch.clone(), renamed])).as_deref(),
+            Some("diff --git a/a.cpp b/a.cpp\n--- a/a.cpp\n+++ b/a.cpp\n@@ -1 +1 @@\n-a\n+b\n")
+        );
+        assert_eq!(interdiff_from_compare(&compare(vec![patch, large])), None);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::LlmLint;
+use crate::errors::Result;
+use util::llm::{LlmClient, LlmExchange, LlmMessage};
+
+/// Prompt to summarize a pull request for reviewers from its commit messages and diff.
+const LLM_PROMPT_OVERVIEW: &str = r#"
+You will be provided with the commit messages of a pull request, followed by its git diff. Summarize what the pull request does for reviewers.
+
+- Write at most five short bullet points, most important first.
+- Describe the effect of the changes, not the individual lines.
+- Only describe what is in the commit messages and the diff. Do not speculate about the motivation.
+- Explicitly mention new or changed RPCs, command line options, P2P messages, and on-disk formats.
+- Classify the pull request as BEHAVIOR_CHANGE, if it changes the observable behavior of the software, as REFACTOR, if it only restructures code, tests, or documentation without changing the observable behavior, or as MIXED, if it does both.
+
+# Output Format
+
+The bullet points, each starting with "- ", followed by a final line with the classification:
+CLASSIFICATION: [BEHAVIOR_CHANGE|REFACTOR|MIXED]
+"#;
+
+/// The labels whose title regexes match any of the commit subjects, sorted by name.
+pub fn touched_subsystems(
+    repo_labels: &std::collections::HashMap<String, Vec<String>>,
+    subjects: &[&str],
+) -> Vec<String> {
+    let mut labels = repo_labels
+        .iter()
+        .filter(|(_, regs)| {
+            regs.iter().any(|reg| {
+                let re = regex::RegexBuilder::new(reg)
+                    .case_insensitive(true)
+                    .build()
+                    .expect("regex config format error");
+                subjects.iter().any(|s| re.is_match(s.trim()))
+            })
+        })
+        .map(|(label, _)| label.clone())
+        .collect::<Vec<_>>();
+    labels.sort();
+    labels
+}
+
+/// Split the reply into the bullet points and the classification.
+fn parse_overview(reply: &str) -> (String, Option<&str>) {
+    let mut bullets = Vec::new();
+    let mut classification = None;
+    for line in reply.lines() {
+        if let Some(c) = line.trim().strip_prefix("CLASSIFICATION:") {
+            classification = Some(match c.trim() {
+                "BEHAVIOR_CHANGE" => "⚠️ Behavior change",
+                "REFACTOR" => "♻️ Refactor (no behavior change)",
+                "MIXED" => "⚠️ Refactor and behavior change",
+                _ => continue,
+            });
+        } else if !line.trim().is_empty() {
+            bullets.push(line);
+        }
+    }
+    (bullets.join("\n"), classification)
+}
+
+/// Return the section text of the overview, along with the exchange for it. The diff is filtered
+/// and limited to a single chunk like for the LLM linter.
+pub async fn get_llm_overview(
+    diff: &str,
+    commit_messages: &[String],
+    subsystems: &[String],
+    head_sha: &str,
+    llm: &LlmClient,
+    lint: &LlmLint,
+) -> Result<(String, LlmExchange)> {
+    println!(" ... Run LLM overview.");
+    let diff = lint.filter_diff(diff);
+    let chunks = util::chunk_diff(&diff, lint.chunk_chars());
+    let truncated = chunks.len() > 1;
+    let diff = chunks.into_iter().next().unwrap_or_default();
+
+    let exchange = llm
+        .exchange(vec![
+            LlmMessage::system(LLM_PROMPT_OVERVIEW),
+            LlmMessage::user(&commit_messages.join("\n\n---\n\n")),
+            LlmMessage::user(&util::prepare_raw_diff_for_llm(&diff)),
+        ])
+        .await?;
+    let (bullets, classification) = parse_overview(&exchange.reply);
+
+    let mut text = "\n### Overview (✨ experimental)\n".to_string();
+    if !subsystems.is_empty() {
+        text += &format!("\nSubsystems: {}\n", subsystems.join(", "));
+    }
+    if let Some(classification) = classification {
+        text += &format!("\nKind: {classification}\n");
+    }
+    text += &format!("\n{bullets}\n");
+    if truncated {
+        text += "\nOnly the first part of the diff was summarized.\n";
+    }
+    text += &format!(
+        "\n<sup>Generated by an LLM from the commit messages and diff as of {head}. It may be inaccurate.</sup>\n",
+        head = &head_sha[..head_sha.len().min(12)],
+    );
+    Ok((text, exchange))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overview() {
+        let (bullets, classification) =
+            parse_overview("- Add the `foo` RPC.\n\n- Refactor `Bar`.\nCLASSIFICATION: MIXED\n");
+        assert_eq!(bullets, "- Add the `foo` RPC.\n- Refactor `Bar`.");
+        assert_eq!(classification, Some("⚠️ Refactor and behavior change"));
+        assert_eq!(parse_overview("- x\nCLASSIFICATION: ?").1, None);
+
+        let labels = std::collections::HashMap::from([
+            ("Tests".to_string(), vec!["^tests?:".to_string()]),
+            ("Validation".to_string(), vec!["^validation:".to_string()]),
+            ("Wallet".to_string(), vec!["^wallet:".to_string()]),
+        ]);
+        assert_eq!(
+            touched_subsystems(&labels, &["validation: Foo", " test: Bar", "doc: Baz"]),
+            vec!["Tests", "Validation"]
+        );
+    }
+}
@@ -1,11 +1,20 @@
+mod ci_failures;
+mod ci_known_failures;
+mod ci_signatures;
 mod config;
 mod errors;
 mod features;
+mod llm_lint;
+mod llm_overview;
+mod reviewers;
 
 use std::str::FromStr;
 
-use crate::features::ci_status::CiStatusFeature;
+use crate::features::ci_status::{CiStatusFeature, HeadIndex};
+use crate::features::commit_checks::CommitChecksFeature;
+use crate::features::commit_hygiene::CommitHygieneFeature;
 use crate::features::labels::LabelsFeature;
+use crate::features::llm_feedback::LlmFeedbackFeature;
 use crate::features::spam_detection::SpamDetectionFeature;
 use crate::features::summary_comment::SummaryCommentFeature;
 use actix_web::{get, post, web, App, HttpRequest, HttpServer, Responder};
@@ -15,6 +24,7 @@ use lazy_static::lazy_static;
 use octocrab::Octocrab;
 use std::collections::BTreeSet;
 use strum::{Display, EnumString};
+use util::llm::{LlmCache, LlmClient};
 
 use crate::config::Config;
 use crate::errors::{DrahtBotError, Result};
@@ -36,6 +46,10 @@ struct Args {
     /// The path to the yaml config file.
     #[arg(long)]
     config_file: std::path::PathBuf,
+    /// The yaml file with the layouts of the metadata comment per repo slug. If not given, the
+    /// builtin util/meta_layouts.yml is used.
+    #[arg(long)]
+    meta_layouts_file: Option<std::path::PathBuf>,
     /// Print changes/edits instead of calling the GitHub/CI API.
     #[arg(long, default_value_t = false)]
     dry_run: bool,
@@ -49,6 +63,8 @@ pub enum GitHubEvent {
     Issues,
     PullRequest,
     PullRequestReview,
+    PullRequestReviewComment,
+    PullRequestReviewThread,
 
     Unknown,
 }
@@ -58,12 +74,21 @@ async fn index() -> &'static str {
     "Welcome to DrahtBot!"
 }
 
+pub struct LlmClients {
+    pub lint: LlmClient,
+    pub ci_reason: LlmClient,
+    pub spam: LlmClient,
+}
+
 pub struct Context {
     octocrab: Octocrab,
     bot_username: String,
     pub config: Config,
     github_token: String,
-    llm_token: String,
+    llm: LlmClients,
+    head_index: HeadIndex,
+    /// Serializes the read-modify-write of the pending LLM feedback file.
+    llm_feedback_lock: tokio::sync::Mutex<()>,
     dry_run: bool,
 }
 
@@ -88,7 +113,10 @@ async fn postreceive_handler(
 fn features() -> Vec<Box<dyn Feature>> {
     vec![
         Box::new(CiStatusFeature::new()),
+        Box::new(CommitChecksFeature::new()),
+        Box::new(CommitHygieneFeature::new()),
         Box::new(LabelsFeature::new()),
+        Box::new(LlmFeedbackFeature::new()),
         Box::new(SpamDetectionFeature::new()),
         Box::new(SummaryCommentFeature::new()),
     ]
@@ -149,11 +177,43 @@ async fn emit_event(ctx: &Context, event: GitHubEvent, data: web::Json<serde_jso
 #[actix_web::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    if let Some(path) = &args.meta_layouts_file {
+        util::MetaLayout::load(path);
+    }
 
     let config: Config = serde_yaml::from_reader(
         std::fs::File::open(args.config_file).expect("config file path error"),
     )
     .expect("yaml error");
+    for repo in &config.repositories {
+        repo.llm_checks
+            .load()
+            .unwrap_or_else(|e| panic!("{}: llm_checks error: {e}", repo.repo_slug));
+        repo.llm_lint
+            .validate()
+            .unwrap_or_else(|e| panic!("{}: llm_lint error: {e}", repo.repo_slug));
+        ci_signatures::Catalogue::load(repo.ci_signatures_file.as_deref())
+            .unwrap_or_else(|e| panic!("{}: ci_signatures_file error: {e}", repo.repo_slug));
+        if let Some(k) = &repo.ci_known_failures {
+            ci_known_failures::KnownFailures::load(&k.file)
+                .unwrap_or_else(|e| panic!("{}: ci_known_failures error: {e}", repo.repo_slug));
+        }
+        for globs in repo.repo_label_paths.values() {
+            util::validate_globs(globs)
+                .unwrap_or_else(|e| panic!("{}: repo_label_paths error: {e}", repo.repo_slug));
+        }
+        if let Some(l) = &repo.needs_backport_label {
+            assert!(
+                l.contains("{branch}"),
+                "{}: needs_backport_label must contain {{branch}}",
+                repo.repo_slug
+            );
+        }
+        if let Some(c) = &repo.commit_checks {
+            c.validate()
+                .unwrap_or_else(|e| panic!("{}: commit_checks error: {e}", repo.repo_slug));
+        }
+    }
 
     let octocrab = octocrab::Octocrab::builder()
         .personal_token(args.token.as_ref())
@@ -173,12 +233,36 @@ async fn main() -> Result<()> {
 
     println!("Running as {bot_username}...");
 
+    let llm_cache = config
+        .llm
+        .cache
+        .clone()
+        .map(|c| std::sync::Arc::new(LlmCache::new(c)));
+    let llm_client = |c: &util::llm::LlmConfig| {
+        let token = match &c.token_env {
+            Some(var) => std::env::var(var).expect("llm token env var must be set"),
+            None => args.llm_token.clone(),
+        };
+        let client = LlmClient::new(c.clone(), &token);
+        match &llm_cache {
+            Some(cache) => client.with_cache(cache.clone()),
+            None => client,
+        }
+    };
+    let llm = LlmClients {
+        lint: llm_client(&config.llm.lint),
+        ci_reason: llm_client(&config.llm.ci_reason),
+        spam: llm_client(&config.llm.spam),
+    };
+
     let context = web::Data::new(Context {
         octocrab,
         bot_username,
         config,
         github_token: args.token,
-        llm_token: args.llm_token,
+        llm,
+        head_index: HeadIndex::default(),
+        llm_feedback_lock: tokio::sync::Mutex::new(()),
         dry_run: args.dry_run,
     });
 
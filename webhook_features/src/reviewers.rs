@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::config::ReviewerSuggestions;
+use crate::errors::Result;
+use crate::Context;
+
+/// Prior ACKs weigh more than authored lines, because they show interest in reviewing.
+const ACK_WEIGHT: usize = 20;
+
+pub struct Suggestion {
+    pub login: String,
+    pub lines: usize,
+    pub acks: usize,
+}
+
+impl Suggestion {
+    fn score(&self) -> usize {
+        self.lines + ACK_WEIGHT * self.acks
+    }
+}
+
+/// Return the old-side line ranges (path, start, count) of all hunks in the diff. The context
+/// lines of the hunks are included, so that pure additions are attributed to the surrounding
+/// code.
+fn parse_old_ranges(diff: &str) -> Vec<(String, usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut path = None;
+    for line in diff.lines() {
+        if let Some(p) = line.strip_prefix("--- ") {
+            path = p.strip_prefix("a/").map(|p| p.to_string());
+        } else if let (Some(p), Some(hunk)) = (&path, line.strip_prefix("@@ -")) {
+            let old = hunk.split_whitespace().next().unwrap_or_default();
+            let (start, count) = old.split_once(',').unwrap_or((old, "1"));
+            if let (Ok(start), Ok(count)) = (start.parse(), count.parse()) {
+                if count > 0 {
+                    ranges.push((p.clone(), start, count));
+                }
+            }
+        }
+    }
+    ranges
+}
+
+/// Return the GitHub logins from the "ACKs for top commit:" list of a merge commit message, as
+/// written by github-merge.py.
+fn parse_merge_acks(message: &str) -> Vec<String> {
+    message
+        .lines()
+        .skip_while(|l| l.trim() != "ACKs for top commit:")
+        .skip(1)
+        .take_while(|l| l.is_empty() || l.starts_with(' '))
+        .filter(|l| l.starts_with("  ") && !l.starts_with("   "))
+        .filter_map(|l| l.trim().strip_suffix(':'))
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// A commit in the git blame output.
+#[derive(Default)]
+struct BlameCommit {
+    mail: String,
+    time: i64,
+    lines: usize,
+}
+
+/// Count the lines per commit in the `git blame --porcelain` output.
+fn parse_blame(porcelain: &str, commits: &mut HashMap<String, BlameCommit>) {
+    let mut sha = None;
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            if let Some(c) = sha.as_ref().and_then(|s| commits.get_mut(s)) {
+                c.lines += 1;
+            }
+        } else if let Some(mail) = line.strip_prefix("author-mail ") {
+            if let Some(c) = sha.as_ref().and_then(|s| commits.get_mut(s)) {
+                c.mail = mail.trim_matches(['<', '>']).to_string();
+            }
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            if let Some(c) = sha.as_ref().and_then(|s| commits.get_mut(s)) {
+                c.time = time.parse().unwrap_or_default();
+            }
+        } else if let Some(s) = line.split(' ').next().filter(|s| s.len() == 40) {
+            commits.entry(s.to_string()).or_default();
+            sha = Some(s.to_string());
+        }
+    }
+}
+
+/// Return the GitHub login of the author of the commit.
+async fn get_login(ctx: &Context, repo_slug: &str, sha: &str, mail: &str) -> Option<String> {
+    if let Some(login) = mail
+        .strip_suffix("@users.noreply.github.com")
+        .map(|m| m.split_once('+').map_or(m, |(_, l)| l))
+    {
+        return Some(login.to_string());
+    }
+    let commit = ctx
+        .octocrab
+        .get::<serde_json::Value, _, ()>(format!("/repos/{repo_slug}/commits/{sha}"), None)
+        .await
+        .ok()?;
+    commit["author"]["login"].as_str().map(|l| l.to_string())
+}
+
+/// Fetch the base branch and the pull request into the local clone, and return the merge base.
+fn fetch(git_dir: &Path, repo_slug: &str, pr_number: u64, base_ref: &str) -> Result<String> {
+    let url = format!("https://github.com/{repo_slug}");
+    if !git_dir.join(".git").is_dir() {
+        println!(" ... Clone {url} to {}", git_dir.display());
+        util::git_output(
+            util::git()
+                .args(["clone", "--quiet", "--no-checkout", &url])
+                .arg(git_dir),
+        )?;
+    } else if util::git_output(
+        util::git()
+            .arg("-C")
+            .arg(git_dir)
+            .args(["config", "remote.origin.partialclonefilter"]),
+    )
+    .is_ok()
+    {
+        // git blame on a partial clone fetches each missing blob in a separate round trip
+        println!(
+            " ... Refetch the partial clone {} in full",
+            git_dir.display()
+        );
+        for key in ["remote.origin.promisor", "remote.origin.partialclonefilter"] {
+            util::git_output(
+                util::git()
+                    .arg("-C")
+                    .arg(git_dir)
+                    .args(["config", "--unset", key]),
+            )?;
+        }
+        util::git_output(util::git().arg("-C").arg(git_dir).args([
+            "fetch",
+            "--quiet",
+            "--refetch",
+            "origin",
+        ]))?;
+    }
+    util::git_output(util::git().arg("-C").arg(git_dir).args([
+        "fetch",
+        "--quiet",
+        "origin",
+        &format!("+refs/heads/{base_ref}:refs/remotes/origin/{base_ref}"),
+        &format!("+refs/pull/{pr_number}/head:refs/remotes/upstream-pull/{pr_number}/head"),
+    ]))?;
+    Ok(util::git_output(util::git().arg("-C").arg(git_dir).args([
+        "merge-base",
+        &format!("origin/{base_ref}"),
+        &format!("upstream-pull/{pr_number}/head"),
+    ]))?
+    .trim()
+    .to_string())
+}
+
+/// Return the blamed commits of the lines touched by the pull request, and the recent merge
+/// commit messages of the touched files. This runs git only, and blocks.
+fn blame(
+    git_dir: &Path,
+    repo_slug: &str,
+    pr_number: u64,
+    base_ref: &str,
+    since_days: u64,
+) -> Result<(HashMap<String, BlameCommit>, String)> {
+    let merge_base = fetch(git_dir, repo_slug, pr_number, base_ref)?;
+    let diff = util::git_output(util::git().arg("-C").arg(git_dir).args([
+        "diff",
+        "--no-renames",
+        &merge_base,
+        &format!("upstream-pull/{pr_number}/head"),
+    ]))?;
+    // One git blame per file, with all its ranges
+    let mut ranges = BTreeMap::<String, Vec<String>>::new();
+    for (path, start, count) in parse_old_ranges(&diff) {
+        let r = ranges.entry(path).or_default();
+        r.push("-L".to_string());
+        r.push(format!("{start},+{count}"));
+    }
+
+    let mut commits = HashMap::new();
+    for (path, args) in &ranges {
+        let porcelain = util::git_output(
+            util::git()
+                .arg("-C")
+                .arg(git_dir)
+                .args(["blame", "--porcelain"])
+                .args(args)
+                .args([&merge_base, "--", path]),
+        )?;
+        parse_blame(&porcelain, &mut commits);
+    }
+    if ranges.is_empty() {
+        return Ok((commits, "".to_string()));
+    }
+    let merges = util::git_output(
+        util::git()
+            .arg("-C")
+            .arg(git_dir)
+            .args([
+                "log",
+                "--first-parent",
+                "--merges",
+                "-n",
+                "200",
+                "--format=%B%x00",
+                &format!("--since={since_days} days ago"),
+                &merge_base,
+                "--",
+            ])
+            .args(ranges.keys()),
+    )?;
+    Ok((commits, merges))
+}
+
+/// Rank the recent authors of the lines touched by the pull request, and the prior ACKers of the
+/// touched files. Users in exclude and bots are skipped.
+pub async fn get_reviewer_suggestions(
+    ctx: &Context,
+    repo_slug: &str,
+    pr_number: u64,
+    base_ref: &str,
+    exclude: &[&str],
+    config: &ReviewerSuggestions,
+) -> Result<Vec<Suggestion>> {
+    println!(" ... Get reviewer suggestions.");
+    let (commits, merges) = {
+        let git_dir = config.git_dir.clone();
+        let repo_slug = repo_slug.to_string();
+        let base_ref = base_ref.to_string();
+        let since_days = config.since_days;
+        actix_web::web::block(move || blame(&git_dir, &repo_slug, pr_number, &base_ref, since_days))
+            .await??
+    };
+    let cutoff =
+        (chrono::Utc::now() - chrono::Duration::days(config.since_days as i64)).timestamp();
+    // Lines and the most recent commit per author mail
+    let mut authors = HashMap::<String, (usize, &str, i64)>::new();
+    for (sha, c) in commits.iter().filter(|(_, c)| c.time >= cutoff) {
+        let a = authors.entry(c.mail.clone()).or_insert((0, sha, c.time));
+        a.0 += c.lines;
+        if c.time > a.2 {
+            (a.1, a.2) = (sha, c.time);
+        }
+    }
+    let mut authors = authors.into_iter().collect::<Vec<_>>();
+    authors.sort_by_key(|(_, (lines, _, _))| std::cmp::Reverse(*lines));
+
+    let is_excluded = |login: &str| {
+        login.ends_with("[bot]")
+            || exclude.iter().any(|e| e.eq_ignore_ascii_case(login))
+            || config.exclude.iter().any(|e| e.eq_ignore_ascii_case(login))
+    };
+    let mut suggestions = HashMap::<String, Suggestion>::new();
+    // Limit the number of API calls
+    for (mail, (lines, sha, _)) in authors.into_iter().take(config.max * 3) {
+        let Some(login) = get_login(ctx, repo_slug, sha, &mail).await else {
+            continue;
+        };
+        if is_excluded(&login) {
+            continue;
+        }
+        suggestions
+            .entry(login.to_lowercase())
+            .or_insert(Suggestion {
+                login,
+                lines: 0,
+                acks: 0,
+            })
+            .lines += lines;
+    }
+    for login in merges.split('\0').flat_map(parse_merge_acks) {
+        if is_excluded(&login) {
+            continue;
+        }
+        suggestions
+            .entry(login.to_lowercase())
+            .or_insert(Suggestion {
+                login,
+                lines: 0,
+                acks: 0,
+            })
+            .acks += 1;
+    }
+
+    let mut suggestions = suggestions.into_values().collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| b.score().cmp(&a.score()).then(a.login.cmp(&b.login)));
+    suggestions.truncate(config.max);
+    Ok(suggestions)
+}
+
+/// Return the section text for the suggestions.
+pub fn render_suggestions(suggestions: &[Suggestion]) -> String {
+    if suggestions.is_empty() {
+        return "".to_string();
+    }
+    let mut text = "\n### Reviewer Suggestions\nBased on `git blame` of the changed lines and prior ACKs on the changed files, the following users may be familiar with the code:\n\n| Reviewer | Recently authored lines | Prior ACKs |\n| -------- | ----------------------- | ---------- |\n".to_string();
+    for s in suggestions {
+        text += &format!("| {} | {} | {} |\n", s.login, s.lines, s.acks);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reviewer_sources() {
+        let diff = "diff --git a/src/a.cpp b/src/a.cpp\n--- a/src/a.cpp\n+++ b/src/a.cpp\n@@ -10,3 +10,4 @@ void f()\n int a;\n+int b;\n@@ -20 +21 @@\n-x\n+y\ndiff --git a/src/new.cpp b/src/new.cpp\n--- /dev/null\n+++ b/src/new.cpp\n@@ -0,0 +1 @@\n+new\n";
+        assert_eq!(
+            parse_old_ranges(diff),
+            vec![
+                ("src/a.cpp".to_string(), 10, 3),
+                ("src/a.cpp".to_string(), 20, 1)
+            ]
+        );
+
+        let merge = "Merge bitcoin/bitcoin#1: wallet: Add foo\n\nPull request description:\n\n  Adds foo.\n\nACKs for top commit:\n  achow101:\n    ACK 0123456\n  furszy:\n    Code review ACK 0123456\n\nTree-SHA512: 0123\n";
+        assert_eq!(parse_merge_acks(merge), vec!["achow101", "furszy"]);
+        assert!(parse_merge_acks("Merge bitcoin/bitcoin#2: Foo\n").is_empty());
+    }
+}